@@ -0,0 +1,242 @@
+use std::io::Write;
+use anyhow::{anyhow, Context};
+use crate::get_progress_style;
+
+const BACKOFF_INITIAL: std::time::Duration = std::time::Duration::from_secs(1);
+const BACKOFF_MAX: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Downloads files over HTTP, resuming partial `.tmp` files with range requests and retrying
+/// transient failures with exponential backoff.
+pub struct Downloader {
+    client: reqwest::blocking::Client,
+    base_url: String,
+    retries: u32,
+}
+
+impl Downloader {
+    pub fn new(base_url: &str, retries: u32) -> anyhow::Result<Downloader> {
+        Ok(Downloader {
+            client: reqwest::blocking::Client::builder().build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            retries,
+        })
+    }
+
+    /// URL of a path relative to the crawl data server, e.g. a line of warc.paths.
+    pub fn remote_path(&self, rel_path: &str) -> String {
+        format!("{}/{}", self.base_url, rel_path)
+    }
+
+    pub fn retrieve_file(&self, remote_path: &str, local_path: &str, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<()> {
+        if std::path::Path::new(&local_path).exists() {
+            return Ok(());
+        }
+
+        let pb = pbm.lock().unwrap().add(indicatif::ProgressBar::with_draw_target(None, indicatif::ProgressDrawTarget::stdout()));
+        pb.set_style(get_progress_style());
+        pb.set_message(remote_path.rsplit('/').next().unwrap().to_string());
+
+        let local_path_temp = format!("{}.tmp", local_path);
+        self.retry(remote_path, &pb, || self.download(remote_path, &local_path_temp, &pb))?;
+        pb.finish();
+
+        std::fs::rename(&local_path_temp, local_path).with_context(|| format!("rename {}", local_path_temp))?;
+        Ok(())
+    }
+
+    /// Sends a GET request for `remote_path` and returns the response for the caller to stream from.
+    /// Only the request is retried; a connection lost while reading the body is an error of the caller's read.
+    pub fn open(&self, remote_path: &str, pb: &indicatif::ProgressBar) -> anyhow::Result<reqwest::blocking::Response> {
+        self.retry(remote_path, pb, || Ok(self.client.get(remote_path).send()?.error_for_status()?))
+    }
+
+    fn retry<T, F>(&self, remote_path: &str, pb: &indicatif::ProgressBar, mut f: F) -> anyhow::Result<T>
+        where F: FnMut() -> anyhow::Result<T> {
+        let mut backoff = BACKOFF_INITIAL;
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.retries && is_retryable(&e) => {
+                    pb.set_message(format!("{} retry {}/{}: {}", remote_path.rsplit('/').next().unwrap(), attempt + 1, self.retries, e));
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(BACKOFF_MAX);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.context(format!("retrieve {}", remote_path))),
+            }
+        }
+    }
+
+    /// One download attempt that continues from whatever is already in `local_path_temp`.
+    fn download(&self, remote_path: &str, local_path_temp: &str, pb: &indicatif::ProgressBar) -> anyhow::Result<()> {
+        let existing = std::fs::metadata(local_path_temp).map(|metadata| metadata.len()).unwrap_or(0);
+        let mut request = self.client.get(remote_path);
+        if existing > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+        }
+        let mut response = request.send()?;
+
+        let status = response.status();
+        let (offset, total) = if status == reqwest::StatusCode::PARTIAL_CONTENT {
+            let (start, total) = parse_content_range(content_range(&response)?)?;
+            if start != existing {
+                return Err(anyhow!("server resumed at byte {} instead of {}", start, existing));
+            }
+            (start, total.or(response.content_length().map(|length| start + length)))
+        } else if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && existing > 0 {
+            // the temporary file is already complete, or longer than the remote file
+            let total = content_range(&response).and_then(parse_content_range).ok().and_then(|(_, total)| total);
+            if total == Some(existing) {
+                pb.set_length(existing);
+                pb.set_position(existing);
+                return Ok(());
+            }
+            // a different or unknown remote size, start over
+            std::fs::remove_file(local_path_temp)?;
+            return self.download(remote_path, local_path_temp, pb);
+        } else {
+            // full response, either a fresh download or a server that ignores range requests
+            response = response.error_for_status()?;
+            (0, response.content_length())
+        };
+
+        let file = match offset {
+            0 => std::fs::File::create(local_path_temp)?,
+            _ => std::fs::OpenOptions::new().append(true).open(local_path_temp)?,
+        };
+        match total {
+            Some(total) => pb.set_length(total),
+            None => pb.unset_length(),
+        }
+        pb.set_position(offset);
+
+        let mut file = pb.wrap_write(file);
+        std::io::copy(&mut response, &mut file)?;
+        file.flush()?;
+
+        let size = std::fs::metadata(local_path_temp)?.len();
+        match total {
+            Some(total) if size != total => Err(anyhow!("downloaded {} bytes, expected {}", size, total)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn content_range(response: &reqwest::blocking::Response) -> anyhow::Result<&str> {
+    Ok(response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .ok_or(anyhow!("missing Content-Range"))?
+        .to_str()?)
+}
+
+/// Parses a Content-Range of `bytes start-end/total` (or `bytes */total`) into the start offset and total size.
+fn parse_content_range(content_range: &str) -> anyhow::Result<(u64, Option<u64>)> {
+    let range = content_range.trim().strip_prefix("bytes ").ok_or(anyhow!("invalid Content-Range {}", content_range))?;
+    let (range, total) = range.split_once('/').ok_or(anyhow!("invalid Content-Range {}", content_range))?;
+    let start = match range.split_once('-') {
+        Some((start, _)) => start.parse().with_context(|| format!("invalid Content-Range {}", content_range))?,
+        None => 0,
+    };
+    Ok((start, total.parse().ok()))
+}
+
+/// Network and I/O failures, timeouts, throttling and server errors are worth retrying;
+/// other client errors such as 404 are not.
+fn is_retryable(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
+        Some(status) => status.is_server_error() || status == reqwest::StatusCode::REQUEST_TIMEOUT || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use super::*;
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    /// Serves `BODY` over HTTP on a local port, honouring `Range: bytes=N-` requests.
+    fn serve(send_content_range: bool, send_content_length: bool) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = vec![];
+                let mut byte = [0u8];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap().to_lowercase();
+                let range_start = request.lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .map(|range| range.trim_end_matches('-').parse::<usize>().unwrap());
+                let (status, content_range, payload) = match range_start {
+                    Some(start) if start < BODY.len() => ("206 Partial Content", Some(format!("bytes {}-{}/{}", start, BODY.len() - 1, BODY.len())), &BODY[start..]),
+                    Some(_) => ("416 Range Not Satisfiable", Some(format!("bytes */{}", BODY.len())).filter(|_| send_content_range), &b""[..]),
+                    None => ("200 OK", None, BODY),
+                };
+                let mut head = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+                if let Some(content_range) = content_range {
+                    head.push_str(&format!("Content-Range: {}\r\n", content_range));
+                }
+                if send_content_length || range_start.is_some() {
+                    head.push_str(&format!("Content-Length: {}\r\n", payload.len()));
+                }
+                head.push_str("\r\n");
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(payload).unwrap();
+            }
+        });
+        url
+    }
+
+    /// Downloads from `url` into a temporary file that starts out holding `partial`.
+    fn download(url: &str, name: &str, partial: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let local_path_temp = std::env::temp_dir().join(format!("magic-rust-{}-{}.tmp", std::process::id(), name));
+        let local_path_temp = local_path_temp.to_str().unwrap();
+        std::fs::write(local_path_temp, partial)?;
+        let downloader = Downloader::new(url, 0)?;
+        downloader.download(url, local_path_temp, &indicatif::ProgressBar::hidden())?;
+        let downloaded = std::fs::read(local_path_temp)?;
+        std::fs::remove_file(local_path_temp)?;
+        Ok(downloaded)
+    }
+
+    #[test]
+    fn parses_content_range() {
+        assert_eq!(parse_content_range("bytes 100-199/1000").unwrap(), (100, Some(1000)));
+        assert_eq!(parse_content_range("bytes 100-199/*").unwrap(), (100, None));
+        assert_eq!(parse_content_range("bytes */1000").unwrap(), (0, Some(1000)));
+        assert!(parse_content_range("items 0-1/2").is_err());
+        assert!(parse_content_range("bytes 0-1").is_err());
+    }
+
+    #[test]
+    fn resumes_partial_download() {
+        let url = serve(true, true);
+        assert_eq!(download(&url, "resume", &BODY[..10]).unwrap(), BODY);
+    }
+
+    #[test]
+    fn keeps_complete_download() {
+        let url = serve(true, true);
+        assert_eq!(download(&url, "complete", BODY).unwrap(), BODY);
+    }
+
+    #[test]
+    fn restarts_on_range_not_satisfiable_without_content_range() {
+        let url = serve(false, true);
+        let too_long = [BODY, b"extra"].concat();
+        assert_eq!(download(&url, "restart", &too_long).unwrap(), BODY);
+    }
+
+    #[test]
+    fn downloads_without_content_length() {
+        let url = serve(true, false);
+        assert_eq!(download(&url, "no-length", b"").unwrap(), BODY);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use anyhow::Context;
+use crate::RecordMetadata;
+
+const SAMPLES_PER_CATEGORY: usize = 5;
+
+/// Failure to extract one WARC record, with whatever is known about the record.
+#[derive(Debug)]
+pub struct RecordError {
+    pub metadata: Option<Box<RecordMetadata>>,
+    /// Record block as read from the WARC, kept for the dead-letter file.
+    pub body: Option<Vec<u8>>,
+    pub error: anyhow::Error,
+}
+
+impl RecordError {
+    pub fn new(error: anyhow::Error) -> RecordError {
+        RecordError { metadata: None, body: None, error }
+    }
+
+    /// Stage that failed: the outermost context attached in `iter_contents`, followed by the
+    /// innermost one naming the step within it, e.g. "extract_content: decode gzip body".
+    pub fn category(&self) -> String {
+        let contexts: Vec<String> = self.error.chain().map(|e| e.to_string()).collect();
+        match contexts.len() {
+            n if n > 2 => format!("{}: {}", contexts[0], contexts[n - 2]),
+            _ => contexts[0].clone(),
+        }
+    }
+
+    /// Reason the record was deliberately not extracted, if that is why it has no document.
+    pub fn skip_reason(&self) -> Option<&str> {
+        self.error.downcast_ref::<Skipped>().map(|skipped| skipped.0.as_str())
+    }
+}
+
+/// Record left out on purpose, e.g. a redirect or an image, rather than one that failed.
+#[derive(Debug)]
+pub struct Skipped(pub String);
+
+impl std::fmt::Display for Skipped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "skipped: {}", self.0)
+    }
+}
+
+impl std::error::Error for Skipped {}
+
+/// Error counts of one WARC file by category, with sample record IDs of each.
+/// Skipped records are counted by reason apart from the errors.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ErrorReport {
+    pub counts: BTreeMap<String, u64>,
+    pub samples: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub skipped: BTreeMap<String, u64>,
+}
+
+impl ErrorReport {
+    pub fn add(&mut self, record_error: &RecordError) {
+        if let Some(reason) = record_error.skip_reason() {
+            return self.skip(reason);
+        }
+        let category = record_error.category();
+        *self.counts.entry(category.clone()).or_insert(0) += 1;
+        if let Some(metadata) = &record_error.metadata {
+            let samples = self.samples.entry(category).or_default();
+            if samples.len() < SAMPLES_PER_CATEGORY {
+                samples.push(metadata.warc_record_id.clone());
+            }
+        }
+    }
+
+    /// Counts a record left out on purpose.
+    pub fn skip(&mut self, reason: &str) {
+        *self.skipped.entry(reason.to_string()).or_insert(0) += 1;
+    }
+}
+
+/// Gzipped WARC file collecting the records that failed to extract, created on the first failure.
+/// Each record keeps its original block and main WARC headers and gains a `WARC-Extract-Error` header.
+pub struct DeadLetter {
+    path: String,
+    writer: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl DeadLetter {
+    pub fn new(path: &str) -> DeadLetter {
+        DeadLetter { path: path.to_string(), writer: None }
+    }
+
+    pub fn write(&mut self, record_error: &RecordError) -> anyhow::Result<()> {
+        let (metadata, body) = match (&record_error.metadata, &record_error.body) {
+            (Some(metadata), Some(body)) => (metadata, body),
+            // records that could not even be read have nothing to write
+            _ => return Ok(()),
+        };
+        if record_error.skip_reason().is_some() {
+            return Ok(());
+        }
+        if self.writer.is_none() {
+            let file = std::fs::File::create(&self.path).with_context(|| format!("create {}", self.path))?;
+            self.writer = Some(std::io::BufWriter::new(file));
+        }
+        let error = format!("{:#}", record_error.error).replace(['\r', '\n'], " ");
+        let mut headers = vec![("WARC-Type", metadata.warc_type.as_str()), ("WARC-Record-ID", metadata.warc_record_id.as_str()), ("WARC-Date", metadata.warc_date.as_str())];
+        if let Some(target_uri) = &metadata.warc_target_uri {
+            headers.push(("WARC-Target-URI", target_uri));
+        }
+        if let Some(content_type) = &metadata.warc_content_type {
+            headers.push(("Content-Type", content_type));
+        }
+        headers.push(("WARC-Extract-Error", &error));
+        crate::output::write_gzip_record(self.writer.as_mut().unwrap(), &headers, body)
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        if let Some(mut writer) = self.writer {
+            std::io::Write::flush(&mut writer)?;
+        }
+        Ok(())
+    }
+}
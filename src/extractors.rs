@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use anyhow::{anyhow, Context};
+use crate::justtext::{Justext, JustextConfig};
+use crate::stoplists::{StoplistSelection, Stoplists};
+use crate::{language, parser, tools, url_tld, ExtractResult, RecordMetadata};
+
+/// Turns the decoded body of an HTTP response into a document.
+pub trait Extractor: Send + Sync {
+    /// `metadata` already holds the WARC and HTTP fields of the record.
+    fn extract(&self, body: &[u8], metadata: RecordMetadata) -> anyhow::Result<ExtractResult>;
+}
+
+/// Extractors by lowercase media type, e.g. "text/html".
+#[derive(Clone)]
+pub struct Extractors {
+    by_media_type: BTreeMap<String, Arc<dyn Extractor>>,
+}
+
+impl Default for Extractors {
+    fn default() -> Extractors {
+        Extractors::new(HtmlExtractor::default())
+    }
+}
+
+impl std::fmt::Debug for Extractors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.by_media_type.keys()).finish()
+    }
+}
+
+impl Extractors {
+    /// HTML, plain text and XML feeds, with `html` for HTML and XHTML.
+    pub fn new(html: HtmlExtractor) -> Extractors {
+        let mut extractors = Extractors { by_media_type: BTreeMap::new() };
+        let html: Arc<dyn Extractor> = Arc::new(html);
+        let xml: Arc<dyn Extractor> = Arc::new(XmlExtractor);
+        extractors.register("text/html", html.clone());
+        extractors.register("application/xhtml+xml", html);
+        extractors.register("text/plain", Arc::new(TextExtractor));
+        for media_type in ["application/xml", "text/xml", "application/rss+xml", "application/atom+xml"] {
+            extractors.register(media_type, xml.clone());
+        }
+        extractors
+    }
+
+    pub fn register(&mut self, media_type: &str, extractor: Arc<dyn Extractor>) {
+        self.by_media_type.insert(media_type.to_ascii_lowercase(), extractor);
+    }
+
+    pub fn get(&self, media_type: &str) -> Option<&dyn Extractor> {
+        self.by_media_type.get(media_type).map(|extractor| extractor.as_ref())
+    }
+
+    /// Drops the extractors of all media types but `media_types`.
+    pub fn retain(&mut self, media_types: &[String]) -> anyhow::Result<()> {
+        let media_types: Vec<String> = media_types.iter().map(|media_type| media_type.to_ascii_lowercase()).collect();
+        if let Some(unknown) = media_types.iter().find(|media_type| !self.by_media_type.contains_key(*media_type)) {
+            return Err(anyhow!("no extractor for media type {}", unknown));
+        }
+        self.by_media_type.retain(|media_type, _| media_types.contains(media_type));
+        Ok(())
+    }
+}
+
+/// libxml HTML parsing and jusText paragraph classification.
+#[derive(Debug, Clone)]
+pub struct HtmlExtractor {
+    /// Identify the language of every paragraph, not just of the document.
+    pub paragraph_language: bool,
+    /// Return the paragraphs with their features along with the text.
+    pub keep_paragraphs: bool,
+    /// Render jusText's HTML debug report of each document.
+    pub debug: bool,
+    pub stoplists: Arc<Stoplists>,
+    pub stoplist_selection: StoplistSelection,
+    pub justext: JustextConfig,
+}
+
+impl Default for HtmlExtractor {
+    /// The bundled English stoplist for every document.
+    fn default() -> HtmlExtractor {
+        let stoplists = Stoplists::bundled();
+        let english = stoplists.get("English").unwrap();
+        HtmlExtractor {
+            paragraph_language: false,
+            keep_paragraphs: false,
+            debug: false,
+            stoplists: Arc::new(stoplists),
+            stoplist_selection: StoplistSelection::Fixed(english),
+            justext: JustextConfig::default(),
+        }
+    }
+}
+
+impl Extractor for HtmlExtractor {
+    fn extract(&self, body: &[u8], mut metadata: RecordMetadata) -> anyhow::Result<ExtractResult> {
+        let tld = metadata.warc_target_uri.as_deref().and_then(url_tld);
+        let (html, encoding) = tools::decode_html(body, metadata.payload_content_type(), tld);
+        metadata.encoding = Some(encoding.to_string());
+
+        // the body is already UTF-8, whatever its meta tags say
+        let parser_options = libxml::parser::ParserOptions { no_blanks: true, no_net: true, ignore_enc: true, ..Default::default() };
+        let xml_parser = libxml::parser::Parser::default_html();
+        let document = xml_parser.parse_string_with_options(html.as_bytes(), parser_options).context("parse HTML")?;
+
+        let mut paragraph_parser = parser::Parser::new();
+        paragraph_parser.m_url = metadata.warc_target_uri.clone().unwrap_or_default();
+        paragraph_parser.walk_tree(&document).context("parse HTML")?;
+        let mut jt = Justext::with_config(&self.justext);
+        jt.set_debug(self.debug);
+        let stoplist = self.stoplists.select(&self.stoplist_selection, &paragraph_parser.m_paragraphs);
+        jt.set_stoplist(stoplist.clone());
+        let content = jt.get_content(&mut paragraph_parser);
+        if self.paragraph_language {
+            for paragraph in paragraph_parser.m_paragraphs.iter_mut() {
+                paragraph.language = language::identify(&paragraph.text);
+            }
+        }
+        let paragraphs = paragraph_parser.m_paragraphs;
+
+        Ok(ExtractResult {
+            metadata,
+            language: None,
+            paragraph_count: paragraphs.len(),
+            good_paragraph_count: paragraphs.iter().filter(|paragraph| paragraph.finalclass == "good").count(),
+            html_length: html.len(),
+            content,
+            stoplist: Some(stoplist.name.clone()),
+            paragraphs: if self.keep_paragraphs { paragraphs } else { vec![] },
+            debug_html: jt.take_debug_output(),
+        })
+    }
+}
+
+/// Plain text, with blank lines separating paragraphs. All paragraphs are kept.
+pub struct TextExtractor;
+
+impl Extractor for TextExtractor {
+    fn extract(&self, body: &[u8], mut metadata: RecordMetadata) -> anyhow::Result<ExtractResult> {
+        let tld = metadata.warc_target_uri.as_deref().and_then(url_tld);
+        let (text, encoding) = tools::decode_html(body, metadata.payload_content_type(), tld);
+        metadata.encoding = Some(encoding.to_string());
+
+        let paragraphs: Vec<String> = text.replace("\r\n", "\n")
+            .split("\n\n")
+            .map(|paragraph| paragraph.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|paragraph| !paragraph.is_empty())
+            .collect();
+        Ok(paragraph_result(metadata, text.len(), paragraphs))
+    }
+}
+
+/// RSS and Atom feeds, one paragraph per item title and text; other XML keeps all text nodes.
+pub struct XmlExtractor;
+
+impl Extractor for XmlExtractor {
+    fn extract(&self, body: &[u8], mut metadata: RecordMetadata) -> anyhow::Result<ExtractResult> {
+        let (xml, encoding) = tools::decode_xml(body, metadata.payload_content_type());
+        metadata.encoding = Some(encoding.to_string());
+
+        let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+        let document = roxmltree::Document::parse_with_options(&xml, options).context("parse XML")?;
+        let items: Vec<roxmltree::Node> = document.descendants()
+            .filter(|node| matches!(node.tag_name().name(), "item" | "entry"))
+            .collect();
+
+        let mut paragraphs = vec![];
+        if items.is_empty() {
+            paragraphs.extend(document.descendants().filter(|node| node.is_text()).filter_map(|node| node.text()).map(normalize_space));
+        }
+        for item in items {
+            for child in item.children().filter(|child| child.is_element()) {
+                let text: String = child.descendants().filter(|node| node.is_text()).filter_map(|node| node.text()).collect();
+                match child.tag_name().name() {
+                    "title" => paragraphs.push(normalize_space(&text)),
+                    // item text is escaped HTML
+                    "description" | "summary" | "content" | "encoded" => paragraphs.push(normalize_space(&html_text(&text)?)),
+                    _ => {}
+                }
+            }
+        }
+        paragraphs.retain(|paragraph| !paragraph.is_empty());
+        Ok(paragraph_result(metadata, xml.len(), paragraphs))
+    }
+}
+
+fn normalize_space(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Text content of an HTML fragment.
+fn html_text(html: &str) -> anyhow::Result<String> {
+    if !html.contains('<') {
+        return Ok(html.to_string());
+    }
+    let parser_options = libxml::parser::ParserOptions { no_net: true, ignore_enc: true, ..Default::default() };
+    let document = libxml::parser::Parser::default_html().parse_string_with_options(html.as_bytes(), parser_options).context("parse HTML")?;
+    Ok(document.get_root_element().map(|root| root.get_content()).unwrap_or_default())
+}
+
+/// Result of an extractor that keeps every paragraph it finds.
+fn paragraph_result(metadata: RecordMetadata, input_length: usize, paragraphs: Vec<String>) -> ExtractResult {
+    ExtractResult {
+        metadata,
+        language: None,
+        paragraph_count: paragraphs.len(),
+        good_paragraph_count: paragraphs.len(),
+        html_length: input_length,
+        content: paragraphs.join("\n"),
+        stoplist: None,
+        paragraphs: vec![],
+        debug_html: None,
+    }
+}
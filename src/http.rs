@@ -0,0 +1,247 @@
+use std::borrow::Cow;
+use std::io::Read;
+use anyhow::{anyhow, Context};
+
+const MAX_HEADERS: usize = 256;
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// Upper bound of a decompressed body, against compression bombs.
+const MAX_BODY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// HTTP response of a WARC response record, with its body de-chunked and decompressed.
+#[derive(Debug)]
+pub struct HttpResponse<'a> {
+    pub status: u16,
+    /// Header fields in message order, names as sent.
+    pub headers: Vec<(String, String)>,
+    pub body: Cow<'a, [u8]>,
+}
+
+impl<'a> HttpResponse<'a> {
+    pub fn parse(message: &'a [u8]) -> anyhow::Result<HttpResponse<'a>> {
+        // httparse only takes HTTP/1.x, so other versions such as "HTTP/2" are read as HTTP/1.1
+        let version_length = message.iter().take(16).position(|&b| b == b' ').filter(|_| !message.starts_with(b"HTTP/1."));
+        let head: Cow<[u8]> = match version_length {
+            Some(version_length) => Cow::Owned([b"HTTP/1.1", &message[version_length..]].concat()),
+            None => Cow::Borrowed(message),
+        };
+        let mut header_buffer = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut header_buffer);
+        // be as lenient as browsers with the malformed heads found in crawls
+        let head_length = match httparse::ParserConfig::default()
+            .allow_spaces_after_header_name_in_responses(true)
+            .allow_obsolete_multiline_headers_in_responses(true)
+            .allow_multiple_spaces_in_response_status_delimiters(true)
+            .ignore_invalid_headers_in_responses(true)
+            .parse_response(&mut response, &head)
+            .context("parse HTTP head")? {
+            httparse::Status::Complete(length) => length,
+            httparse::Status::Partial => return Err(anyhow!("incomplete HTTP head")),
+        };
+        // back to an offset into `message`
+        let head_length = match version_length {
+            Some(version_length) => head_length - b"HTTP/1.1".len() + version_length,
+            None => head_length,
+        };
+        let status = response.code.ok_or(anyhow!("no HTTP status"))?;
+        let headers = response.headers.iter()
+            .map(|header| (header.name.to_string(), String::from_utf8_lossy(header.value).trim().to_string()))
+            .collect();
+
+        let mut http_response = HttpResponse { status, headers, body: Cow::Borrowed(&message[head_length..]) };
+        if http_response.has_token("transfer-encoding", "chunked") {
+            // crawlers often store the payload de-chunked but keep the header
+            if let Some(body) = dechunk(&http_response.body) {
+                http_response.body = Cow::Owned(body);
+            }
+        }
+        let content_encodings: Vec<String> = http_response.header_tokens("content-encoding");
+        for content_encoding in content_encodings.iter().rev() {
+            if let Some(body) = decode(&http_response.body, content_encoding) {
+                http_response.body = Cow::Owned(body);
+            }
+        }
+        Ok(http_response)
+    }
+
+    /// Value of the first header named `name`, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// Lowercase comma-separated tokens of all headers named `name`.
+    fn header_tokens(&self, name: &str) -> Vec<String> {
+        self.headers.iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .map(|token| token.trim().to_ascii_lowercase())
+            .filter(|token| !token.is_empty())
+            .collect()
+    }
+
+    fn has_token(&self, name: &str, token: &str) -> bool {
+        self.header_tokens(name).iter().any(|t| t == token)
+    }
+
+    /// Lowercase media type of the Content-Type header without parameters, e.g. "text/html".
+    pub fn media_type(&self) -> Option<String> {
+        let media_type = self.header("content-type")?.split(';').next()?.trim().to_ascii_lowercase();
+        Some(media_type).filter(|media_type| !media_type.is_empty())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Joins the chunks of a chunked body, or `None` if the body is not validly chunked.
+fn dechunk(body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n")?;
+        let size_line = std::str::from_utf8(&rest[..line_end]).ok()?;
+        let size = usize::from_str_radix(size_line.split(';').next()?.trim(), 16).ok()?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            // trailers are dropped
+            return Some(decoded);
+        }
+        if rest.len() < size {
+            // truncated payload, keep what arrived
+            decoded.extend_from_slice(rest);
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(&rest[..size]);
+        rest = &rest[size..];
+        rest = rest.strip_prefix(b"\r\n").unwrap_or(rest);
+        if rest.is_empty() {
+            // last chunk missing
+            return Some(decoded);
+        }
+    }
+}
+
+/// Undoes one content coding; `None` means the body is left as it is. Stored payloads are often
+/// already decompressed, so unknown codings and bodies that fail to decode are kept too.
+fn decode(body: &[u8], content_encoding: &str) -> Option<Vec<u8>> {
+    let reader: Box<dyn Read + '_> = match content_encoding {
+        "gzip" | "x-gzip" if body.starts_with(GZIP_MAGIC) => Box::new(flate2::read::MultiGzDecoder::new(body)),
+        "deflate" => match body.first() {
+            // zlib-wrapped as the RFC says, or raw deflate as some servers send it
+            Some(&b) if b & 0x0f == 8 && (((b as u16) << 8) | *body.get(1).unwrap_or(&0) as u16).is_multiple_of(31) => Box::new(flate2::read::ZlibDecoder::new(body)),
+            _ => Box::new(flate2::read::DeflateDecoder::new(body)),
+        },
+        "br" => Box::new(brotli::Decompressor::new(body, 4096)),
+        _ => return None,
+    };
+    let mut decoded = Vec::new();
+    match reader.take(MAX_BODY_BYTES).read_to_end(&mut decoded) {
+        Ok(_) => Some(decoded),
+        // a truncated gzip member still decodes to the start of the payload
+        Err(_) if body.starts_with(GZIP_MAGIC) && !decoded.is_empty() => Some(decoded),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+
+    const HTML: &[u8] = b"<html><body><p>Hello, world!</p></body></html>";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn dechunks_chunks() {
+        assert_eq!(dechunk(b"5\r\nHello\r\n7\r\n, world\r\n0\r\n\r\n").unwrap(), b"Hello, world");
+    }
+
+    #[test]
+    fn dechunks_with_extensions_and_trailers() {
+        assert_eq!(dechunk(b"5;name=value\r\nHello\r\n0\r\nExpires: never\r\n\r\n").unwrap(), b"Hello");
+        assert_eq!(dechunk(b"A \r\n0123456789\r\n0\r\n\r\n").unwrap(), b"0123456789");
+    }
+
+    #[test]
+    fn dechunks_truncated_bodies() {
+        // truncated inside a chunk
+        assert_eq!(dechunk(b"5\r\nHello\r\n7\r\n, wo").unwrap(), b"Hello, wo");
+        // last chunk missing
+        assert_eq!(dechunk(b"5\r\nHello\r\n").unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn does_not_dechunk_unchunked_bodies() {
+        assert_eq!(dechunk(HTML), None);
+        assert_eq!(dechunk(b"Hello\r\nworld"), None);
+    }
+
+    #[test]
+    fn decodes_gzip() {
+        assert_eq!(decode(&gzip(HTML), "gzip").unwrap(), HTML);
+        assert_eq!(decode(&gzip(HTML), "x-gzip").unwrap(), HTML);
+        // stored decoded
+        assert_eq!(decode(HTML, "gzip"), None);
+    }
+
+    #[test]
+    fn decodes_truncated_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::none());
+        encoder.write_all(HTML).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let decoded = decode(&compressed[..compressed.len() - 12], "gzip").unwrap();
+        assert!(HTML.starts_with(&decoded));
+    }
+
+    #[test]
+    fn decodes_zlib_and_raw_deflate() {
+        let mut zlib = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        zlib.write_all(HTML).unwrap();
+        assert_eq!(decode(&zlib.finish().unwrap(), "deflate").unwrap(), HTML);
+        let mut raw = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+        raw.write_all(HTML).unwrap();
+        assert_eq!(decode(&raw.finish().unwrap(), "deflate").unwrap(), HTML);
+    }
+
+    #[test]
+    fn decodes_brotli() {
+        let mut compressed = vec![];
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+            encoder.write_all(HTML).unwrap();
+        }
+        assert_eq!(decode(&compressed, "br").unwrap(), HTML);
+    }
+
+    #[test]
+    fn keeps_undecodable_bodies() {
+        assert_eq!(decode(HTML, "br"), None);
+        assert_eq!(decode(HTML, "deflate"), None);
+        assert_eq!(decode(HTML, "zstd"), None);
+        assert_eq!(decode(HTML, "utf-8"), None);
+        assert_eq!(decode(HTML, "identity"), None);
+    }
+
+    #[test]
+    fn parses_lenient_heads() {
+        let message = b"HTTP/2 200\r\nContent-Type : text/html\r\nX-Folded: a\r\n b\r\nBad Header\r\n\r\nbody";
+        let response = HttpResponse::parse(message).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.media_type().as_deref(), Some("text/html"));
+        assert_eq!(&response.body[..], b"body");
+    }
+
+    #[test]
+    fn parses_chunked_gzip_body() {
+        let compressed = gzip(HTML);
+        let mut message = format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Encoding: gzip\r\n\r\n{:x}\r\n", compressed.len()).into_bytes();
+        message.extend_from_slice(&compressed);
+        message.extend_from_slice(b"\r\n0\r\n\r\n");
+        assert_eq!(&HttpResponse::parse(&message).unwrap().body[..], HTML);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::Path;
+use anyhow::{anyhow, Context};
+
+const WARC_EXTENSIONS: [&str; 3] = [".warc.gz", ".warc.zst", ".warc"];
+
+const WARC_MAGIC: &[u8] = b"WARC/";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+// skippable frame holding the dictionary at the start of a .warc.zst file
+const ZSTD_DICTIONARY_MAGIC: &[u8] = &[0x5d, 0x2a, 0x4d, 0x18];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarcFormat {
+    Warc,
+    Gzip,
+    Zstd,
+}
+
+/// Detects the WARC container format from the magic bytes at the start of `reader` without consuming them.
+pub fn detect_format<R: BufRead>(reader: &mut R) -> anyhow::Result<WarcFormat> {
+    let magic = reader.fill_buf()?;
+    if magic.is_empty() {
+        Err(anyhow!("empty WARC file"))
+    } else if magic.starts_with(WARC_MAGIC) {
+        Ok(WarcFormat::Warc)
+    } else if magic.starts_with(GZIP_MAGIC) {
+        Ok(WarcFormat::Gzip)
+    } else if magic.starts_with(ZSTD_MAGIC) || magic.starts_with(ZSTD_DICTIONARY_MAGIC) {
+        Ok(WarcFormat::Zstd)
+    } else {
+        Err(anyhow!("unsupported WARC format, magic bytes {:02x?}", &magic[..magic.len().min(8)]))
+    }
+}
+
+/// Wraps `reader` in the decoder matching its format and returns a WARC reader over the decoded stream.
+pub fn open_warc_reader<R: BufRead + 'static>(mut reader: R) -> anyhow::Result<warc::WarcReader<Box<dyn BufRead>>> {
+    let decoded: Box<dyn BufRead> = match detect_format(&mut reader)? {
+        WarcFormat::Warc => Box::new(reader),
+        WarcFormat::Gzip => {
+            let gzip_stream = libflate::gzip::MultiDecoder::new(reader)?;
+            Box::new(std::io::BufReader::new(gzip_stream))
+        }
+        WarcFormat::Zstd => {
+            let zstd_stream = match reader.fill_buf()?.starts_with(ZSTD_DICTIONARY_MAGIC) {
+                true => {
+                    let dictionary = read_zstd_dictionary(&mut reader)?;
+                    zstd::stream::read::Decoder::with_dictionary(reader, &dictionary)?
+                }
+                false => zstd::stream::read::Decoder::with_buffer(reader)?,
+            };
+            Box::new(std::io::BufReader::new(zstd_stream))
+        }
+    };
+    Ok(warc::WarcReader::new(decoded))
+}
+
+fn read_zstd_dictionary<R: BufRead>(reader: &mut R) -> anyhow::Result<Vec<u8>> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).context("zstd dictionary frame header")?;
+    let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let mut dictionary = vec![0u8; size];
+    reader.read_exact(&mut dictionary).context("zstd dictionary")?;
+    // the dictionary itself may be zstd-compressed
+    if dictionary.starts_with(ZSTD_MAGIC) {
+        dictionary = zstd::stream::decode_all(&dictionary[..]).context("zstd dictionary")?;
+    }
+    Ok(dictionary)
+}
+
+pub fn is_warc_file(path: &str) -> bool {
+    WARC_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+/// File name without its WARC extension, e.g. "crawl.part1" for "crawl.part1.warc.gz".
+pub fn warc_stem(filename: &str) -> &str {
+    WARC_EXTENSIONS.iter().find_map(|ext| filename.strip_suffix(ext)).unwrap_or(filename)
+}
+
+/// Local WARC file named on the command line or found under a named directory.
+#[derive(Debug, Clone)]
+pub struct LocalWarc {
+    pub path: String,
+    /// Stem of the output files: the path below the named directory with `_` for `/`, else the
+    /// file name, without the WARC extension.
+    pub stem: String,
+}
+
+/// Expands files, directories and glob patterns into a list of local WARC files sorted by path.
+/// Directories are searched recursively; explicitly named files are kept whatever their extension.
+/// Fails if two files would write the same output files.
+pub fn expand_inputs(inputs: &[String]) -> anyhow::Result<Vec<LocalWarc>> {
+    let mut warc_paths = vec![];
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            walk_dir(path, path, &mut warc_paths)?;
+        } else if path.is_file() {
+            warc_paths.push(local_warc(path, path.parent().unwrap_or(Path::new(""))));
+        } else {
+            let mut matched = false;
+            for entry in glob::glob(input).with_context(|| format!("glob {}", input))? {
+                let entry = entry?;
+                if entry.is_dir() {
+                    walk_dir(&entry, &entry, &mut warc_paths)?;
+                } else {
+                    warc_paths.push(local_warc(&entry, entry.parent().unwrap_or(Path::new(""))));
+                }
+                matched = true;
+            }
+            if !matched {
+                return Err(anyhow!("no such file, directory or glob match: {}", input));
+            }
+        }
+    }
+    warc_paths.sort_by(|a, b| a.path.cmp(&b.path));
+    warc_paths.dedup_by(|a, b| a.path == b.path);
+    let mut paths_by_stem: BTreeMap<&str, &str> = BTreeMap::new();
+    for warc in &warc_paths {
+        if let Some(other_path) = paths_by_stem.insert(&warc.stem, &warc.path) {
+            return Err(anyhow!("{} and {} would both write output {}", other_path, warc.path, warc.stem));
+        }
+    }
+    Ok(warc_paths)
+}
+
+fn local_warc(path: &Path, root: &Path) -> LocalWarc {
+    let relative_path = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+    LocalWarc { path: path.to_string_lossy().to_string(), stem: warc_stem(&relative_path).replace('/', "_") }
+}
+
+fn walk_dir(dir: &Path, root: &Path, warc_paths: &mut Vec<LocalWarc>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("read_dir {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_dir(&path, root, warc_paths)?;
+        } else if is_warc_file(&path.to_string_lossy()) {
+            warc_paths.push(local_warc(&path, root));
+        }
+    }
+    Ok(())
+}
@@ -1,23 +1,248 @@
-use std::collections::HashSet;
-use std::fs::{File, read_to_string};
-use std::io::{BufRead, Read};
+use std::sync::Arc;
+use anyhow::Context;
 use lazy_static::lazy_static;
 use regex::Regex;
-use crate::parser::{Paragraph, Parser};
+use crate::parser::{Paragraph, Parser, PARSER_RE3};
+use crate::stoplists::Stoplist;
 
 const MAX_LINK_DENSITY_DEFAULT: f32 = 0.2;
-const LENGTH_LOW_DEFAULT: i32 = 70;
-const LENGTH_HIGH_DEFAULT: i32 = 200;
+const LENGTH_LOW_DEFAULT: i64 = 70;
+const LENGTH_HIGH_DEFAULT: i64 = 200;
 const STOPWORDS_LOW_DEFAULT: f32 = 0.30;
 const STOPWORDS_HIGH_DEFAULT: f32 = 0.32;
 const NO_HEADINGS_DEFAULT: bool = false;
 const MAX_HEADING_DISTANCE_DEFAULT: i32 = 200;
+const WRAP_WIDTH_DEFAULT: usize = 80;
+
+/// Paragraph classification thresholds of jusText.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JustextConfig {
+    /// Paragraphs shorter than this many characters are "short".
+    pub length_low: i64,
+    /// Paragraphs longer than this many characters with enough stopwords are "good".
+    pub length_high: i64,
+    pub stopwords_low: f32,
+    pub stopwords_high: f32,
+    /// Paragraphs with more of their characters inside links are "bad".
+    pub max_link_density: f32,
+    /// Characters after a short heading within which a good paragraph makes it good too.
+    pub max_heading_distance: i32,
+    pub no_headings: bool,
+    pub output: JustextOutput,
+    /// Line width of tagged output, 0 for no wrapping.
+    pub wrap_width: usize,
+}
+
+impl Default for JustextConfig {
+    fn default() -> JustextConfig {
+        JustextConfig {
+            length_low: LENGTH_LOW_DEFAULT,
+            length_high: LENGTH_HIGH_DEFAULT,
+            stopwords_low: STOPWORDS_LOW_DEFAULT,
+            stopwords_high: STOPWORDS_HIGH_DEFAULT,
+            max_link_density: MAX_LINK_DENSITY_DEFAULT,
+            max_heading_distance: MAX_HEADING_DISTANCE_DEFAULT,
+            no_headings: NO_HEADINGS_DEFAULT,
+            output: JustextOutput::default(),
+            wrap_width: WRAP_WIDTH_DEFAULT,
+        }
+    }
+}
+
+impl JustextConfig {
+    pub fn preset(preset: JustextPreset) -> JustextConfig {
+        match preset {
+            JustextPreset::Default => JustextConfig::default(),
+            JustextPreset::Strict => JustextConfig::default()
+                .length_low(100)
+                .length_high(250)
+                .stopwords_low(0.35)
+                .stopwords_high(0.40)
+                .max_link_density(0.1),
+            JustextPreset::RecallOriented => JustextConfig::default()
+                .length_low(50)
+                .length_high(150)
+                .stopwords_low(0.20)
+                .stopwords_high(0.25)
+                .max_link_density(0.4)
+                .max_heading_distance(300),
+        }
+    }
+
+    pub fn length_low(mut self, length_low: i64) -> JustextConfig {
+        self.length_low = length_low;
+        self
+    }
+
+    pub fn length_high(mut self, length_high: i64) -> JustextConfig {
+        self.length_high = length_high;
+        self
+    }
+
+    pub fn stopwords_low(mut self, stopwords_low: f32) -> JustextConfig {
+        self.stopwords_low = stopwords_low;
+        self
+    }
+
+    pub fn stopwords_high(mut self, stopwords_high: f32) -> JustextConfig {
+        self.stopwords_high = stopwords_high;
+        self
+    }
+
+    pub fn max_link_density(mut self, max_link_density: f32) -> JustextConfig {
+        self.max_link_density = max_link_density;
+        self
+    }
+
+    pub fn max_heading_distance(mut self, max_heading_distance: i32) -> JustextConfig {
+        self.max_heading_distance = max_heading_distance;
+        self
+    }
+
+    pub fn no_headings(mut self, no_headings: bool) -> JustextConfig {
+        self.no_headings = no_headings;
+        self
+    }
+
+    pub fn output(mut self, output: JustextOutput) -> JustextConfig {
+        self.output = output;
+        self
+    }
+
+    pub fn wrap_width(mut self, wrap_width: usize) -> JustextConfig {
+        self.wrap_width = wrap_width;
+        self
+    }
+
+    /// Applies the values set in `overrides`.
+    pub fn with_overrides(self, overrides: &JustextOverrides) -> JustextConfig {
+        let mut config = self;
+        if let Some(length_low) = overrides.length_low {
+            config = config.length_low(length_low);
+        }
+        if let Some(length_high) = overrides.length_high {
+            config = config.length_high(length_high);
+        }
+        if let Some(stopwords_low) = overrides.stopwords_low {
+            config = config.stopwords_low(stopwords_low);
+        }
+        if let Some(stopwords_high) = overrides.stopwords_high {
+            config = config.stopwords_high(stopwords_high);
+        }
+        if let Some(max_link_density) = overrides.max_link_density {
+            config = config.max_link_density(max_link_density);
+        }
+        if let Some(max_heading_distance) = overrides.max_heading_distance {
+            config = config.max_heading_distance(max_heading_distance);
+        }
+        if let Some(no_headings) = overrides.no_headings {
+            config = config.no_headings(no_headings);
+        }
+        if let Some(output) = overrides.output {
+            config = config.output(output);
+        }
+        if let Some(wrap_width) = overrides.wrap_width {
+            config = config.wrap_width(wrap_width);
+        }
+        config
+    }
+
+    /// Starts from the preset of `cli`, else of `file`, then applies `file` and `cli` values in turn.
+    pub fn resolve(file: Option<&JustextOverrides>, cli: &JustextOverrides) -> anyhow::Result<JustextConfig> {
+        let preset = cli.preset.or(file.and_then(|file| file.preset)).unwrap_or(JustextPreset::Default);
+        let mut config = JustextConfig::preset(preset);
+        if let Some(file) = file {
+            config = config.with_overrides(file);
+        }
+        let config = config.with_overrides(cli);
+        if config.length_low > config.length_high || config.stopwords_low > config.stopwords_high {
+            return Err(anyhow::anyhow!("jusText low thresholds above high ones: {:?}", config));
+        }
+        Ok(config)
+    }
+
+    /// Reads a TOML file of `JustextOverrides`, e.g. `preset = "strict"` and `length_low = 80`.
+    pub fn read_file(path: &str) -> anyhow::Result<JustextOverrides> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("read {}", path))?;
+        toml::from_str(&text).with_context(|| format!("parse {}", path))
+    }
+}
+
+/// Named sets of jusText thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JustextPreset {
+    /// the thresholds of the original jusText
+    Default,
+    /// longer paragraphs with more stopwords and fewer links, for cleaner text
+    Strict,
+    /// shorter paragraphs with fewer stopwords, keeping more text
+    RecallOriented,
+}
+
+/// How jusText writes the paragraphs of a document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JustextOutput {
+    /// the text of good paragraphs, joined by spaces
+    #[default]
+    Plain,
+    /// good paragraphs one per block, tagged <h> for headings, <l> for list items and <p> otherwise
+    NoBoilerplate,
+    /// like no-boilerplate, with the other paragraphs tagged <b>
+    Full,
+}
+
+/// jusText values set on the command line or in a config file, overriding the preset.
+#[derive(Debug, Clone, Default, clap::Args, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JustextOverrides {
+    /// jusText parameter preset [default: default]
+    #[clap(long = "justext-preset", value_enum)]
+    pub preset: Option<JustextPreset>,
+
+    /// Paragraphs shorter than this many characters are short [default: 70]
+    #[clap(long)]
+    pub length_low: Option<i64>,
+
+    /// Paragraphs longer than this many characters can be good on their own [default: 200]
+    #[clap(long)]
+    pub length_high: Option<i64>,
+
+    /// Stopword density below which paragraphs are bad [default: 0.30]
+    #[clap(long)]
+    pub stopwords_low: Option<f32>,
+
+    /// Stopword density from which paragraphs can be good [default: 0.32]
+    #[clap(long)]
+    pub stopwords_high: Option<f32>,
+
+    /// Share of characters inside links above which paragraphs are bad [default: 0.2]
+    #[clap(long)]
+    pub max_link_density: Option<f32>,
+
+    /// Characters after a short heading searched for good paragraphs [default: 200]
+    #[clap(long)]
+    pub max_heading_distance: Option<i32>,
+
+    /// Do not treat headings specially
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    pub no_headings: Option<bool>,
+
+    /// Document text format [default: plain]
+    #[clap(long = "justext-output", value_enum)]
+    pub output: Option<JustextOutput>,
+
+    /// Line width of no-boilerplate and full output, 0 for no wrapping [default: 80]
+    #[clap(long)]
+    pub wrap_width: Option<usize>,
+}
 
 lazy_static! {
-    pub static ref JUSTEXT_RE1 : Regex = Regex::new(r"(^h\\d|\\.h\\d)").unwrap();
-    pub static ref JUSTEXT_RE2 : Regex = Regex::new(r"(^li|\\.li)").unwrap();
-    pub static ref JUSTEXT_RE3 : Regex = Regex::new(r"(^select|\\.select)").unwrap();
-    pub static ref STOPLIST : HashSet<String> = read_to_string("stoplists/English.txt").unwrap().lines().map(|s| s.to_string()).collect();
+    pub static ref JUSTEXT_RE1 : Regex = Regex::new(r"(^h\d|\.h\d)").unwrap();
+    pub static ref JUSTEXT_RE2 : Regex = Regex::new(r"(^li|\.li)").unwrap();
+    pub static ref JUSTEXT_RE3 : Regex = Regex::new(r"(^select|\.select)").unwrap();
+    pub static ref JUSTEXT_RE4 : Regex = Regex::new(r"\n[ \t]*\n\s*").unwrap();
 }
 
 #[derive(Debug, Clone, Default)]
@@ -28,31 +253,60 @@ pub struct Justext {
     m_stopwords_high: f32,
     m_max_link_density: f32,
     m_no_headings: bool,
+    m_max_heading_distance: i32,
+    m_output: JustextOutput,
+    m_wrap_width: usize,
     m_debug: bool,
+    m_debug_output: Option<String>,
+    m_stoplist: Arc<Stoplist>,
 }
 
 impl Justext {
-    pub fn new() -> Justext {
-        Justext::default()
+    pub fn with_config(config: &JustextConfig) -> Justext {
+        Justext {
+            m_length_low: config.length_low,
+            m_length_high: config.length_high,
+            m_stopwords_low: config.stopwords_low,
+            m_stopwords_high: config.stopwords_high,
+            m_max_link_density: config.max_link_density,
+            m_no_headings: config.no_headings,
+            m_max_heading_distance: config.max_heading_distance,
+            m_output: config.output,
+            m_wrap_width: config.wrap_width,
+            ..Justext::default()
+        }
+    }
+
+    pub fn set_stoplist(&mut self, stoplist: Arc<Stoplist>) {
+        self.m_stoplist = stoplist;
+    }
+
+    /// Makes `get_content` render a debug report, see `take_debug_output`.
+    pub fn set_debug(&mut self, debug: bool) {
+        self.m_debug = debug;
+    }
+
+    /// HTML debug report of the last `get_content` call in debug mode.
+    pub fn take_debug_output(&mut self) -> Option<String> {
+        self.m_debug_output.take()
     }
 
     pub fn get_content(&mut self, parser: &mut Parser) -> String {
         self.classify_paragraphs(&mut parser.m_paragraphs);
-        self.revise_paragraph_classification(&mut parser.m_paragraphs, MAX_HEADING_DISTANCE_DEFAULT);
-        // if self.m_debug {
-        //     self.make_debug_output(fsm.get_para(), "test/debugJusText.html", url, encoding);
-        // }
-        self.output_default(&mut parser.m_paragraphs)
+        self.revise_paragraph_classification(&mut parser.m_paragraphs, self.m_max_heading_distance);
+        if self.m_debug {
+            self.m_debug_output = Some(self.make_debug_output(&parser.m_paragraphs, &parser.m_url));
+        }
+        self.output_default(&parser.m_paragraphs)
     }
 
     fn classify_paragraphs(&mut self, paragraphs: &mut Vec<Paragraph>) {
         for paragraph in paragraphs.iter_mut() {
-            let length = paragraph.text.len() as i64;
-            let stopword_count = paragraph.text.split(|c : char| c.is_whitespace()).filter(|s| STOPLIST.contains(*s)).count();
-
-            let words: Vec<String> = paragraph.text.split(|c : char| c.is_whitespace()).map(|s| s.to_string()).collect();
-            let stopword_density = stopword_count as f32 / words.len() as f32;
+            let length = paragraph.text.chars().count() as i64;
+            let (stopword_count, word_count) = self.m_stoplist.count(&paragraph.text);
+            let stopword_density = stopword_count as f32 / word_count as f32;
             let link_density = paragraph.linked_char_count as f32 / length as f32;
+            paragraph.word_count = word_count as i64;
             paragraph.stopword_count = stopword_count as i64;
             paragraph.stopword_density = stopword_density;
             paragraph.link_density = link_density;
@@ -117,7 +371,7 @@ impl Justext {
                     paragraphs[i].finalclass = "neargood";
                     break;
                 }
-                distance += paragraphs[j].text.len() as i32;
+                distance += paragraphs[j].text.chars().count() as i32;
                 j += 1;
             }
         }
@@ -176,80 +430,115 @@ impl Justext {
                     paragraphs[i].finalclass = "good";
                     break;
                 }
-                distance += paragraphs[j].text.len() as i32;
+                distance += paragraphs[j].text.chars().count() as i32;
                 j += 1;
             }
         }
     }
 
-    fn output_default(&self, paragraphs: &mut Vec<Paragraph>) -> String {
+    /// Renders the paragraphs as an HTML page coloured by final class, with their features in tooltips.
+    fn make_debug_output(&self, paragraphs: &[Paragraph], url: &str) -> String {
         let mut out = String::new();
-        for mut paragraph in paragraphs {
-            let &mut tag;
-            if paragraph.finalclass == "good" {
-                if paragraph.heading {
-                    tag = "h";
-                } else if paragraph.bullet {
-                    tag = "l";
-                } else {
-                    tag = "p";
+        out.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>jusText ");
+        out.push_str(&escape_html(url));
+        out.push_str("</title>\n<style>\n");
+        out.push_str("body { font-family: sans-serif; max-width: 60em; margin: auto; }\n");
+        out.push_str("p { padding: 0.3em; margin: 0.3em 0; border-left: 0.4em solid transparent; }\n");
+        out.push_str("p.good { background: #d9f2d9; } p.bad { background: #f8d7d7; color: #666; }\n");
+        out.push_str("p.neargood { background: #fff3c4; } p.short { background: #e6e6e6; }\n");
+        out.push_str("p.cf-good { border-color: #2e8b2e; } p.cf-bad { border-color: #c0392b; }\n");
+        out.push_str("p.cf-neargood { border-color: #d4a017; } p.cf-short { border-color: #888; }\n");
+        out.push_str("p.heading { font-weight: bold; } p.bullet::before { content: \"\\2022 \"; }\n");
+        out.push_str("</style></head><body>\n");
+        out.push_str(&format!("<h1>{}</h1>\n", escape_html(url)));
+        out.push_str(&format!(
+            "<p>stoplist {} ({:?}), length {}-{}, stopwords {}-{}, max link density {}, max heading distance {}, {} paragraphs, {} good</p>\n",
+            escape_html(&self.m_stoplist.name), self.m_stoplist.segmentation, self.m_length_low, self.m_length_high,
+            self.m_stopwords_low, self.m_stopwords_high, self.m_max_link_density, self.m_max_heading_distance,
+            paragraphs.len(), paragraphs.iter().filter(|paragraph| paragraph.finalclass == "good").count()));
+        out.push_str("<p>Background: final class; left border: context-free class. Hover a paragraph for its features.</p>\n<hr>\n");
+        for paragraph in paragraphs {
+            let mut classes = vec![paragraph.finalclass.to_string(), format!("cf-{}", paragraph.cfclass)];
+            if paragraph.heading {
+                classes.push("heading".to_string());
+            }
+            if paragraph.bullet {
+                classes.push("bullet".to_string());
+            }
+            let tooltip = format!(
+                "final: {}\ncontext-free: {}\nstopword density: {:.3} ({} of {} words)\nlink density: {:.3}\nlength: {}\ntags: {}\nDOM path: {}",
+                paragraph.finalclass, paragraph.cfclass, paragraph.stopword_density, paragraph.stopword_count, paragraph.word_count,
+                paragraph.link_density, paragraph.text.chars().count(), paragraph.tag_count, paragraph.dom_path);
+            out.push_str(&format!("<p class=\"{}\" title=\"{}\">{}</p>\n", classes.join(" "), escape_html(&tooltip), escape_html(&paragraph.text)));
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+
+    fn output_default(&self, paragraphs: &[Paragraph]) -> String {
+        let mut out = String::new();
+        for paragraph in paragraphs {
+            let tag = if paragraph.finalclass != "good" {
+                if self.m_output != JustextOutput::Full {
+                    continue;
                 }
-                // if !full {
-                    out.push_str(&paragraph.text);
-                    out.push_str(" ");
-                // }
+                "b"
+            } else if paragraph.heading {
+                "h"
+            } else if paragraph.bullet {
+                "l"
             } else {
-                // if no_boilerplate {
-                //     continue;
-                // } else {
-                //     tag = "b";
-                // }
+                "p"
+            };
+            if self.m_output == JustextOutput::Plain {
+                out.push_str(&paragraph.text);
+                out.push_str(" ");
+                continue;
+            }
+            if !out.is_empty() {
+                out.push_str("\n\n");
+            }
+            out.push_str(format!("<{}>", tag).as_str());
+            if paragraph.m_tag == "pre" {
+                // blank lines start new paragraphs, other lines are kept as they are
+                out.push_str(&JUSTEXT_RE4.replace_all(paragraph.text.trim_matches('\n'), format!("\n\n<{}>", tag)));
+            } else {
+                out.push_str(&wrap_text(&PARSER_RE3.replace_all(paragraph.text.trim(), " "), self.m_wrap_width));
             }
-            // replace_a_to_b(&mut paragraph.text, "&nbsp;", " ");
-            // replace_a_to_b(&mut paragraph.text, "&quot;", "\"");
-            // replace_a_to_b(&mut paragraph.text, "&gt;", ">");
-            // replace_a_to_b(&mut paragraph.text, "&lt;", "<");
-            // if full {
-            //     let tmp = wrap_text(&mut paragraph.text, 80);
-            //     if paragraph.m_tag == "pre" {
-            //         let re = regex::Regex::new(r"\n>?[ \t]?\n>?").unwrap();
-            //         out.push_str(&re.replace_all(&tmp, format!("\n\n<{}>", tag).to_string()));
-            //     } else {
-            //         out.push_str(format!("<{}>", tag).as_str());
-            //         out.push_str(&tmp);
-            //         out.push_str("\n\n");
-            //     }
-            // }
         }
         out
     }
 }
 
-fn wrap_text(str: &mut String, len: usize) -> &str {
-    let mut line_width = 0;
-    let mut last_space = 0;
-    let mut next_s = 0;
-    let mut next_n = 0;
-    for mut i in 0..str.len() {
-        next_s = str[i..].find(' ').unwrap_or(str.len() - i);
-        if str[next_s..next_s + 1] == *"\n" {
-            line_width = 0;
-            last_space = next_s;
-            i += next_s + 1;
-            continue;
-        }
-
-        line_width += next_s - last_space + 1;
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
 
-        if line_width > len {
-            str.insert_str(last_space + 1, "\n");
-            line_width = next_s - last_space + 1;
+/// Breaks the lines of `text` at spaces to at most `width` characters where words allow, if `width` is not 0.
+fn wrap_text(text: &str, width: usize) -> String {
+    if width == 0 {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let mut line_width = 0;
+        for word in line.split(' ').filter(|word| !word.is_empty()) {
+            let word_width = word.chars().count();
+            if line_width > 0 && line_width + 1 + word_width > width {
+                out.push('\n');
+                line_width = 0;
+            } else if line_width > 0 {
+                out.push(' ');
+                line_width += 1;
+            }
+            out.push_str(word);
+            line_width += word_width;
         }
-
-        i += next_s + 1;
-        last_space = next_s;
     }
-    str
+    out
 }
 
 fn _get_neighbour(i: i64, paragraphs: &mut Vec<Paragraph>, ignore_neargood: bool, inc: i64, boundary: i64) -> String {
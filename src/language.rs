@@ -0,0 +1,12 @@
+/// Label of documents and paragraphs whose language could not be identified.
+pub const UNDETERMINED: &str = "und";
+
+/// Language of `text` as an ISO 639-3 code, e.g. "eng" or "hun", from whatlang's trigram
+/// profiles; `None` when the text is too short or too mixed to tell reliably.
+pub fn identify(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    match info.is_reliable() {
+        true => Some(info.lang().code()),
+        false => None,
+    }
+}
@@ -3,59 +3,376 @@
 mod justtext;
 mod tools;
 mod parser;
+mod input;
+mod output;
+mod manifest;
+mod download;
+mod pipeline;
+mod errors;
+mod http;
+mod extractors;
+mod records;
+mod language;
+mod stoplists;
+mod segmentation;
 
 use clap::Parser;
 
 use std::any::Any;
 use std::fs::File;
-use std::io::{BufRead, Write};
+use std::io::BufRead;
 use anyhow::{anyhow, Context};
 use rayon::prelude::*;
-use crate::justtext::Justext;
+use crate::download::Downloader;
+use crate::errors::{DeadLetter, ErrorReport, RecordError, Skipped};
+use crate::extractors::{Extractors, HtmlExtractor};
+use crate::justtext::{JustextConfig, JustextOverrides};
+use crate::http::HttpResponse;
+use crate::manifest::Manifest;
+use crate::output::{Compression, OutputFile, OutputFormat, OutputOptions};
+use crate::pipeline::PipelineOptions;
+use crate::records::{BlockKind, PayloadCache, PendingSegments, RecordAssembler};
+use crate::segmentation::Segmentation;
+use crate::stoplists::{StoplistSelection, Stoplists};
 
 const CC_REMOTE_PATH: &str = "https://data.commoncrawl.org";
-const LOCAL_BASE_PATH: &str = "/Users/igor/Downloads/magic";
-const WARCS_REL_PATH: &str = "crawl-data/CC-MAIN-2023-23/warc.paths.gz";
+const CRAWL_ID_DEFAULT: &str = "CC-MAIN-2023-23";
 
 #[derive(clap_derive::Parser)]
 struct Cli {
     #[clap(short, long, default_value_t = false)]
     debug: bool,
+
+    /// Common Crawl crawl ID, e.g. CC-MAIN-2023-23
+    #[clap(long, default_value = CRAWL_ID_DEFAULT)]
+    crawl: String,
+
+    /// Local warc.paths file (plain or .gz) used instead of the crawl's published list
+    #[clap(long)]
+    warc_paths: Option<String>,
+
+    /// Root directory for downloaded files; WARCs are stored under <data-dir>/warcs
+    #[clap(long, default_value = ".")]
+    data_dir: String,
+
+    /// Directory for extracted output [default: <data-dir>/extract]
+    #[clap(long)]
+    output_dir: Option<String>,
+
+    /// Local WARC files, directories or glob patterns to extract instead of downloading a crawl
+    #[clap(long, num_args = 1..)]
+    input: Vec<String>,
+
+    /// Output file format
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Compression of text, jsonl and paragraphs output files
+    #[clap(long, value_enum, default_value_t = Compression::None)]
+    compress: Compression,
+
+    /// Rotate output into numbered files of about this size, e.g. 256M
+    #[clap(long, value_parser = output::parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Rotate output into numbered files of at most this many documents
+    #[clap(long)]
+    max_file_docs: Option<u64>,
+
+    /// Base URL that warc.paths entries are relative to
+    #[clap(long, default_value = CC_REMOTE_PATH)]
+    remote_url: String,
+
+    /// Extract WARCs while they download instead of storing them under <data-dir>/warcs first
+    #[clap(long, default_value_t = false)]
+    stream: bool,
+
+    /// Number of concurrent WARC downloads
+    #[clap(long, default_value_t = 4)]
+    download_workers: usize,
+
+    /// Number of concurrent WARC extractions [default: number of CPUs]
+    #[clap(long)]
+    extract_workers: Option<usize>,
+
+    /// Number of downloaded WARCs queued for extraction; each download worker may hold one
+    /// more, so up to queue-depth + download-workers WARCs can wait on disk
+    #[clap(long, default_value_t = 2)]
+    queue_depth: usize,
+
+    /// Number of threads extracting the records of each WARC
+    #[clap(long, default_value_t = 1)]
+    record_workers: usize,
+
+    /// Write documents as soon as they are extracted instead of in WARC order
+    #[clap(long, default_value_t = false)]
+    unordered: bool,
+
+    /// Write records that fail to extract to <output-dir>/<warc>_dead_letter.warc.gz
+    #[clap(long, default_value_t = false)]
+    dead_letter: bool,
+
+    /// Bytes of earlier records kept for resolving revisit records, e.g. 256M; 0 skips revisits
+    #[clap(long, default_value = "64M", value_parser = output::parse_size)]
+    revisit_cache_size: u64,
+
+    /// Keep only documents in these languages, as ISO 639-3 codes, e.g. eng,hun; "und" keeps unidentified ones
+    #[clap(long, value_delimiter = ',')]
+    languages: Option<Vec<String>>,
+
+    /// Write the documents of each language to their own <warc>_<language>_extract files
+    #[clap(long, default_value_t = false)]
+    split_by_language: bool,
+
+    /// Identify the language of each paragraph of HTML documents as well
+    #[clap(long, default_value_t = false)]
+    paragraph_language: bool,
+
+    /// Stoplist of the HTML documents: a language name such as English, "language" for the stoplist of
+    /// the identified language, or "density" for the one with the most stopwords
+    #[clap(long, default_value = "English")]
+    stoplist: String,
+
+    /// Directory of <Language>.txt stoplists used alongside the bundled English and Hungarian ones
+    #[clap(long)]
+    stoplist_dir: Option<String>,
+
+    /// Word segmentation for stopword counting [default: characters for Chinese, Japanese, Thai,
+    /// Lao, Khmer and Burmese stoplists, whitespace otherwise]
+    #[clap(long, value_enum)]
+    segmentation: Option<Segmentation>,
+
+    /// TOML file of jusText parameters, e.g. preset = "strict" and length_low = 80; options given on
+    /// the command line take precedence
+    #[clap(long)]
+    justext_config: Option<String>,
+
+    #[clap(flatten)]
+    justext: JustextOverrides,
+
+    /// Write the jusText debug report of the record with this WARC-Record-ID or target URI in the
+    /// --input WARC files instead of extracting them
+    #[clap(long)]
+    debug_record: Option<String>,
+
+    /// Path of the --debug-record report [default: <output-dir>/debug.html]
+    #[clap(long)]
+    debug_output: Option<String>,
+
+    /// Media types to extract, e.g. text/html,text/plain [default: all with an extractor]
+    #[clap(long = "media-type", value_delimiter = ',')]
+    media_types: Option<Vec<String>>,
+
+    /// Number of retries of a failed download
+    #[clap(long, default_value_t = 5)]
+    retries: u32,
+
+    /// Number of WARC paths to skip (applied after --shard)
+    #[clap(long, default_value_t = 0)]
+    offset: usize,
+
+    /// Number of WARC paths to process [default: 10, or 1 with --debug; all --input files]
+    #[clap(long)]
+    count: Option<usize>,
+
+    /// Process only every Nth WARC path, starting at the ith one, e.g. 3/16
+    #[clap(long)]
+    shard: Option<Shard>,
+}
+
+/// `i/N` partition of the WARC path list: keeps the paths whose position modulo N is i.
+#[derive(Debug, Clone, Copy)]
+struct Shard {
+    index: usize,
+    total: usize,
+}
+
+impl std::str::FromStr for Shard {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Shard> {
+        let (index, total) = s.split_once('/').ok_or(anyhow!("expected i/N, got {}", s))?;
+        let index: usize = index.trim().parse().with_context(|| format!("shard index {}", index))?;
+        let total: usize = total.trim().parse().with_context(|| format!("shard total {}", total))?;
+        if total == 0 || index >= total {
+            return Err(anyhow!("shard index must be less than shard total, got {}", s));
+        }
+        Ok(Shard { index, total })
+    }
+}
+
+/// Local directories derived from the command line.
+#[derive(Debug, Clone)]
+struct Dirs {
+    warcs: String,
+    extract: String,
+}
+
+impl Dirs {
+    fn from_cli(args: &Cli) -> Dirs {
+        let data_dir = args.data_dir.trim_end_matches('/');
+        Dirs {
+            warcs: format!("{}/warcs", data_dir),
+            extract: args.output_dir.clone().unwrap_or_else(|| format!("{}/extract", data_dir)),
+        }
+    }
+
+    fn create(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.warcs).with_context(|| format!("create {}", self.warcs))?;
+        std::fs::create_dir_all(&self.extract).with_context(|| format!("create {}", self.extract))?;
+        Ok(())
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
 
-    let n_warc_paths = match args.debug {
-        true => 1,
-        false => 10,
+    let n_warc_paths = match (args.count, args.debug) {
+        (Some(count), _) => count,
+        (None, true) => 1,
+        (None, false) if !args.input.is_empty() => usize::MAX,
+        (None, false) => 10,
     };
 
     let pbm = indicatif::MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::stdout());
     let pbm = std::sync::Arc::new(std::sync::Mutex::new(pbm));
     // let pb1 = pbm.add(indicatif::ProgressBar::with_draw_target(None, indicatif::ProgressDrawTarget::stderr()));
 
-    // retrieve warcs.path.gz
-    let warcs_filename = WARCS_REL_PATH.split('/').last().unwrap();
-    let warcs_remote_path = format!("{}/{}", CC_REMOTE_PATH, WARCS_REL_PATH);
-    let warcs_local_path = format!("{}/warcs/{}", LOCAL_BASE_PATH, warcs_filename);
-    retrieve_file(&warcs_remote_path, &warcs_local_path, &pbm)?;
+    let dirs = Dirs::from_cli(&args);
+    let mut stoplists = Stoplists::bundled();
+    if let Some(stoplist_dir) = &args.stoplist_dir {
+        stoplists.load_dir(stoplist_dir)?;
+    }
+    if let Some(segmentation) = args.segmentation {
+        stoplists.set_segmentation(segmentation);
+    }
+    let stoplist_selection = match args.stoplist.as_str() {
+        "language" => StoplistSelection::Language,
+        "density" => StoplistSelection::Density,
+        name => StoplistSelection::Fixed(stoplists.get(name)?),
+    };
+    let justext_file = args.justext_config.as_deref().map(JustextConfig::read_file).transpose()?;
+    let mut extractors = Extractors::new(HtmlExtractor {
+        paragraph_language: args.paragraph_language,
+        keep_paragraphs: matches!(args.format, OutputFormat::Paragraphs | OutputFormat::Wet),
+        debug: args.debug_record.is_some(),
+        stoplists: std::sync::Arc::new(stoplists),
+        stoplist_selection,
+        justext: JustextConfig::resolve(justext_file.as_ref(), &args.justext)?,
+    });
+    if let Some(media_types) = &args.media_types {
+        extractors.retain(media_types)?;
+    }
+    let extract_options = ExtractOptions {
+        output: OutputOptions {
+            format: args.format,
+            compression: args.compress,
+            max_file_bytes: args.max_file_size,
+            max_file_docs: args.max_file_docs,
+            split_by_language: args.split_by_language,
+        },
+        record_workers: args.record_workers,
+        ordered: !args.unordered,
+        dead_letter: args.dead_letter,
+        languages: args.languages.clone(),
+        extractors: std::sync::Arc::new(extractors),
+        payloads: std::sync::Arc::new(PayloadCache::new(args.revisit_cache_size)),
+        segments: std::sync::Arc::new(PendingSegments::default()),
+    };
+
+    if let Some(debug_record) = &args.debug_record {
+        let warc_local_paths: Vec<String> = input::expand_inputs(&args.input)?.into_iter().map(|warc| warc.path).collect();
+        if warc_local_paths.is_empty() {
+            return Err(anyhow!("--debug-record needs --input WARC files"));
+        }
+        std::fs::create_dir_all(&dirs.extract).with_context(|| format!("create {}", dirs.extract))?;
+        let debug_output = args.debug_output.clone().unwrap_or_else(|| format!("{}/debug.html", dirs.extract));
+        write_debug_report(&warc_local_paths, debug_record, &debug_output, &extract_options)?;
+        eprintln!("wrote {}", debug_output);
+        return Ok(());
+    }
+
+    // extract local warc files without any download step
+    if !args.input.is_empty() {
+        std::fs::create_dir_all(&dirs.extract).with_context(|| format!("create {}", dirs.extract))?;
+        let manifest = Manifest::open(&format!("{}/manifest.jsonl", dirs.extract))?;
+        let local_warcs = input::expand_inputs(&args.input)?;
+        let local_warcs: Vec<input::LocalWarc> = select_warc_paths(local_warcs.into_iter(), args.shard, args.offset, n_warc_paths)
+            .filter(|local_warc| !manifest.is_completed(&local_warc.path))
+            .collect();
+        let failures: Vec<(String, anyhow::Error)> = local_warcs.into_par_iter().filter_map(|local_warc| {
+            let summary = extract_warc_file(&local_warc.path, &local_warc.stem, &dirs, &extract_options, &pbm);
+            let result = summary.and_then(|summary| manifest.complete(&local_warc.path, summary.records, summary.documents, summary.files, summary.errors));
+            result.err().map(|e| (local_warc.path, e))
+        }).collect();
+        return report_failures(&failures, &extract_options.segments, &pbm);
+    }
+
+    dirs.create()?;
+    let downloader = Downloader::new(&args.remote_url, args.retries)?;
+    let manifest = Manifest::open(&format!("{}/manifest.jsonl", dirs.extract))?;
+
+    // retrieve warc.paths.gz unless an explicit list was given
+    let warcs_local_path = match &args.warc_paths {
+        Some(warc_paths) => warc_paths.clone(),
+        None => {
+            let warcs_rel_path = format!("crawl-data/{}/warc.paths.gz", args.crawl);
+            let warcs_remote_path = downloader.remote_path(&warcs_rel_path);
+            let warcs_local_path = format!("{}/{}.warc.paths.gz", dirs.warcs, args.crawl);
+            downloader.retrieve_file(&warcs_remote_path, &warcs_local_path, &pbm)?;
+            warcs_local_path
+        }
+    };
 
     // extract list of warc files
     let warc_path_iterator = read_warc_paths(&warcs_local_path)?;
-    let mut warc_paths: Vec<String> = warc_path_iterator.take(n_warc_paths).collect();
-
-    // retrieve warc files
-    warc_paths.clone().into_par_iter().for_each(|warc_path| {
-        retrieve_warc_file(&warc_path, &pbm).unwrap();
-    });
+    let warc_paths: Vec<String> = select_warc_paths(warc_path_iterator, args.shard, args.offset, n_warc_paths)
+        .filter(|warc_path| !manifest.is_completed(warc_path))
+        .collect();
+
+    // extract warc files straight from the network
+    if args.stream {
+        let failures: Vec<(String, anyhow::Error)> = warc_paths.into_par_iter().filter_map(|warc_path| {
+            let summary = stream_warc_file(&downloader, &warc_path, &dirs, &extract_options, &pbm);
+            let result = summary.and_then(|summary| manifest.complete(&warc_path, summary.records, summary.documents, summary.files, summary.errors));
+            result.err().map(|e| (warc_path, e))
+        }).collect();
+        return report_failures(&failures, &extract_options.segments, &pbm);
+    }
 
-    // extract warc files
-    warc_paths.clone().into_par_iter().for_each(|warc_path| {
-        extract_warc_file(&warc_path, &pbm).unwrap();
-    });
+    // retrieve and extract warc files, downloading ahead of the extract workers
+    let pipeline_options = PipelineOptions {
+        download_workers: args.download_workers,
+        extract_workers: args.extract_workers.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+        queue_depth: args.queue_depth,
+    };
+    let failures = pipeline::run(warc_paths, &pipeline_options,
+        |warc_path| retrieve_warc_file(&downloader, warc_path, &dirs, &pbm),
+        |warc_path| {
+            let warc_filename = warc_path.rsplit('/').next().unwrap();
+            let warc_local_path = format!("{}/{}", dirs.warcs, warc_filename);
+            let summary = extract_warc_file(&warc_local_path, input::warc_stem(warc_filename), &dirs, &extract_options, &pbm)?;
+            manifest.complete(warc_path, summary.records, summary.documents, summary.files, summary.errors)
+        });
+    report_failures(&failures, &extract_options.segments, &pbm)
+}
 
-    Ok(())
+/// Prints the WARC files that failed and the segmented records whose other segments were in
+/// none of the WARCs of the run; fails if a WARC failed.
+fn report_failures(failures: &[(String, anyhow::Error)], segments: &PendingSegments, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<()> {
+    for (warc_path, e) in failures {
+        pbm.lock().unwrap().suspend(|| eprintln!("{}: {:#}", warc_path, e));
+    }
+    for record_error in segments.finish() {
+        pbm.lock().unwrap().suspend(|| match &record_error.metadata {
+            Some(metadata) => eprintln!("{}: {:#}", metadata.warc_record_id, record_error.error),
+            None => eprintln!("{:#}", record_error.error),
+        });
+    }
+    match failures.len() {
+        0 => Ok(()),
+        n => Err(anyhow!("{} WARC files failed", n)),
+    }
 }
 
 fn get_progress_style() -> indicatif::ProgressStyle {
@@ -63,41 +380,117 @@ fn get_progress_style() -> indicatif::ProgressStyle {
         "{spinner:.green} [{elapsed_precise}] {msg} {bytes}/{total_bytes} {bytes_per_sec} {eta} [{wide_bar:.cyan/blue}]").unwrap()
 }
 
+/// WARC and HTTP header fields of the record a document was extracted from.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+struct RecordMetadata {
+    /// WARC-Type of the record the block comes from; a resolved revisit takes that of its original.
+    #[serde(skip)]
+    warc_type: String,
+    warc_record_id: String,
+    warc_date: String,
+    warc_target_uri: Option<String>,
+    warc_content_type: Option<String>,
+    http_status: Option<u16>,
+    http_content_type: Option<String>,
+    /// Encoding the HTML was decoded from.
+    encoding: Option<String>,
+    /// All HTTP response headers, for later stages.
+    #[serde(skip)]
+    http_headers: Vec<(String, String)>,
+}
+
+impl RecordMetadata {
+    fn from_record(record: &warc::Record<warc::BufferedBody>) -> RecordMetadata {
+        RecordMetadata {
+            warc_type: record.warc_type().to_string(),
+            warc_record_id: record.warc_id().to_string(),
+            warc_date: record.header(warc::WarcHeader::Date).unwrap_or_default().to_string(),
+            warc_target_uri: record.header(warc::WarcHeader::TargetURI).map(|s| s.to_string()),
+            warc_content_type: record.header(warc::WarcHeader::ContentType).map(|s| s.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Content-Type of the payload: the HTTP one of a response, the WARC one of a resource record.
+    fn payload_content_type(&self) -> Option<&str> {
+        match self.http_status {
+            Some(_) => self.http_content_type.as_deref(),
+            None => self.warc_content_type.as_deref(),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ExtractResult {
+    metadata: RecordMetadata,
+    language: Option<String>,
+    paragraph_count: usize,
+    good_paragraph_count: usize,
+    html_length: usize,
     content: String,
+    /// Stoplist jusText classified the paragraphs with.
+    stoplist: Option<String>,
+    /// Every paragraph with its features and classes, kept only for the paragraphs output.
+    paragraphs: Vec<parser::Paragraph>,
+    /// jusText's HTML debug report, in --debug-record mode.
+    debug_html: Option<String>,
     // content_annotated: String,
 }
 
-fn extract_content(http_response: &str) -> anyhow::Result<ExtractResult> {
-    let http_body_start_index = http_response.find("\r\n\r\n").ok_or(anyhow::anyhow!("no newline found"))?;
-    let http_body = &http_response[http_body_start_index + 4..];
-
-    let mut parser_options = libxml::parser::ParserOptions::default();
-    parser_options.no_blanks = true;
-    parser_options.no_net = true;
-    let xml_parser = libxml::parser::Parser::default_html();
-    let document = xml_parser.parse_string_with_options(http_body, parser_options)?;
+/// Parses the HTTP response and hands its body to the extractor of its media type.
+fn extract_content(http_response: &[u8], mut metadata: RecordMetadata, extractors: &Extractors) -> anyhow::Result<ExtractResult> {
+    let http_response = HttpResponse::parse(http_response)?;
+    metadata.http_status = Some(http_response.status);
+    metadata.http_content_type = http_response.header("content-type").map(|s| s.to_string());
+    metadata.http_headers = http_response.headers.clone();
+    if !http_response.is_success() {
+        return Err(anyhow!(Skipped(format!("status {}xx", http_response.status / 100))));
+    }
+    // a missing Content-Type is taken as HTML
+    let media_type = http_response.media_type().unwrap_or_else(|| "text/html".to_string());
+    let extractor = extractors.get(&media_type).ok_or_else(|| anyhow!(Skipped(format!("media type {}", media_type))))?;
+    extractor.extract(&http_response.body, metadata)
+}
 
-    let mut paragraph_parser = parser::Parser::new();
-    paragraph_parser.walk_tree(&document)?;
-    let mut jt = Justext::new();
-    let content = jt.get_content(&mut paragraph_parser);
+/// Hands the block of a `resource` or `conversion` record to the extractor of its WARC Content-Type.
+fn extract_payload(payload: &[u8], metadata: RecordMetadata, extractors: &Extractors) -> anyhow::Result<ExtractResult> {
+    let media_type = metadata.payload_content_type().and_then(|content_type| content_type.split(';').next()).unwrap_or_default().trim().to_ascii_lowercase();
+    let extractor = extractors.get(&media_type).ok_or_else(|| anyhow!(Skipped(format!("media type {}", media_type))))?;
+    extractor.extract(payload, metadata)
+}
 
-    Ok(ExtractResult {
-        content,
-        // annotated_html: annotated.to_string(),
-    })
+/// Settings for extracting one WARC file.
+#[derive(Debug, Clone)]
+struct ExtractOptions {
+    output: OutputOptions,
+    /// Threads running `extract_content` on the records of the WARC.
+    record_workers: usize,
+    /// Keep the documents of a WARC in record order when `record_workers` > 1.
+    ordered: bool,
+    /// Keep the raw records that fail to extract.
+    dead_letter: bool,
+    /// Languages of the documents to keep, all if `None`.
+    languages: Option<Vec<String>>,
+    extractors: std::sync::Arc<Extractors>,
+    /// Earlier payloads of the run that `revisit` records can refer to.
+    payloads: std::sync::Arc<PayloadCache>,
+    /// Segmented records of the run still missing segments, which may be in a later WARC.
+    segments: std::sync::Arc<PendingSegments>,
 }
 
-fn extract_warc_file(warc_path: &str, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<()> {
-    let write_extracted = true;
-    let write_annotated = false;
+/// Outcome of extracting one WARC file.
+#[derive(Debug)]
+struct ExtractSummary {
+    records: u64,
+    documents: u64,
+    files: Vec<OutputFile>,
+    errors: ErrorReport,
+}
 
-    let warc_filename = warc_path.split('/').last().unwrap();
-    let warc_local_path = format!("{}/warcs/{}", LOCAL_BASE_PATH, warc_filename);
+fn extract_warc_file(warc_local_path: &str, warc_stem: &str, dirs: &Dirs, options: &ExtractOptions, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<ExtractSummary> {
+    let warc_filename = warc_local_path.rsplit('/').next().unwrap();
 
-    let warc_file = File::open(&warc_local_path)?;
+    let warc_file = File::open(warc_local_path).with_context(|| format!("open {}", warc_local_path))?;
     let pb = pbm.lock().unwrap().add(indicatif::ProgressBar::with_draw_target(None, indicatif::ProgressDrawTarget::stdout()));
     pb.set_style(get_progress_style());
     pb.set_message(format!(" {}", warc_filename).clone());
@@ -106,26 +499,71 @@ fn extract_warc_file(warc_path: &str, pbm: &std::sync::Arc<std::sync::Mutex<indi
     let warc_file = pb.wrap_read(warc_file);
 
     let file_reader = std::io::BufReader::with_capacity(1_048_576, warc_file);
-    let gzip_stream = libflate::gzip::MultiDecoder::new(file_reader)?;
-    let gzip_reader = std::io::BufReader::new(gzip_stream);
-    let warc_reader = warc::WarcReader::new(gzip_reader);
+    extract_warc_reader(file_reader, warc_filename, warc_stem, dirs, options, pbm).with_context(|| format!("read {}", warc_local_path))
+}
+
+/// Extracts a WARC file from the crawl's server as it downloads, without writing the WARC to disk.
+fn stream_warc_file(downloader: &Downloader, warc_path: &str, dirs: &Dirs, options: &ExtractOptions, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<ExtractSummary> {
+    let warc_filename = warc_path.rsplit('/').next().unwrap();
+    let warc_remote_path = downloader.remote_path(warc_path);
+
+    let pb = pbm.lock().unwrap().add(indicatif::ProgressBar::with_draw_target(None, indicatif::ProgressDrawTarget::stdout()));
+    pb.set_style(get_progress_style());
+    pb.set_message(format!(" {}", warc_filename).clone());
+
+    let response = downloader.open(&warc_remote_path, &pb)?;
+    match response.content_length() {
+        Some(length) => pb.set_length(length),
+        None => pb.unset_length(),
+    }
+    let response = pb.wrap_read(response);
+
+    let response_reader = std::io::BufReader::with_capacity(1_048_576, response);
+    extract_warc_reader(response_reader, warc_filename, input::warc_stem(warc_filename), dirs, options, pbm).with_context(|| format!("read {}", warc_remote_path))
+}
+
+fn extract_warc_reader<R>(reader: R, warc_filename: &str, warc_stem: &str, dirs: &Dirs, options: &ExtractOptions, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<ExtractSummary>
+    where R: BufRead + 'static {
+    let write_extracted = true;
+    let write_annotated = false;
 
-    let mut extract_file = std::fs::File::create(format!("{}/extract/{}_extract", LOCAL_BASE_PATH, warc_filename.split('.').next().unwrap()))?;
+    let warc_reader = input::open_warc_reader(reader)?;
+
+    let mut records = 0;
+    let mut documents = 0;
+    let mut errors = ErrorReport::default();
+    let mut dead_letter = match options.dead_letter {
+        true => Some(DeadLetter::new(&format!("{}/{}_dead_letter.warc.gz", dirs.extract, warc_stem))),
+        false => None,
+    };
+    let mut extract_sink = output::create_sink(&dirs.extract, warc_stem, warc_filename, &options.output)?;
+    // first failed write, which fails the WARC; later documents are not written
+    let mut write_error: Option<anyhow::Error> = None;
     // let mut extracted_file = libflate::gzip::Encoder::new(&mut extracted_file).unwrap();
 
     // let mut extracted_file = pb.wrap_write(extracted_file);
-    // let mut annotated_file = std::fs::File::create(format!("{}/annotated.html", LOCAL_BASE_PATH)).unwrap();
-    iter_contents(warc_reader, pbm, |parse_result| {
+    // let mut annotated_file = std::fs::File::create(format!("{}/annotated.html", dirs.extract)).unwrap();
+    iter_contents(warc_reader, options, pbm, |parse_result| {
+        records += 1;
         match parse_result {
             Ok(extract_result) => {
                 // pb2.inc(1);
                 // println!("record {}", result.text_record.len());
-                let extract_content = &extract_result.content;
-                if write_extracted && !extract_content.is_empty() {
+                let has_output = match options.output.format {
+                    // the paragraphs output keeps documents without good paragraphs too
+                    OutputFormat::Paragraphs => !extract_result.content.is_empty() || !extract_result.paragraphs.is_empty(),
+                    OutputFormat::Wet => extract_result.good_paragraph_count > 0,
+                    _ => !extract_result.content.is_empty(),
+                };
+                let language = extract_result.language.as_deref().unwrap_or(language::UNDETERMINED);
+                if has_output && options.languages.as_ref().is_some_and(|languages| !languages.iter().any(|l| l == language)) {
+                    errors.skip(&format!("language {}", language));
+                } else if write_extracted && has_output && write_error.is_none() {
                     // let extract_content = &extract_result.content.replace("\n", " ");
-                    extract_file.write_all(format!("{}\t", extract_content.len()).as_bytes()).unwrap();
-                    extract_file.write_all(extract_content.as_bytes()).unwrap();
-                    extract_file.write_all(b"\n").unwrap();
+                    match extract_sink.write(&extract_result) {
+                        Ok(()) => documents += 1,
+                        Err(e) => write_error = Some(e.context("write output")),
+                    }
                 }
                 if write_annotated {
                     // annotated_file
@@ -134,15 +572,86 @@ fn extract_warc_file(warc_path: &str, pbm: &std::sync::Arc<std::sync::Mutex<indi
                     // annotated_file.write_all(b"<hr>").unwrap();
                 }
             }
-            Err(e) => {}
+            Err(record_error) => {
+                errors.add(&record_error);
+                if let Some(dead_letter) = dead_letter.as_mut().filter(|_| write_error.is_none()) {
+                    if let Err(e) = dead_letter.write(&record_error) {
+                        write_error = Some(e.context("write dead letter"));
+                    }
+                }
+            }
         }
     });
+    if let Some(e) = write_error {
+        return Err(e);
+    }
+    let files = extract_sink.finish()?;
+    if let Some(dead_letter) = dead_letter {
+        dead_letter.finish()?;
+    }
+
+    Ok(ExtractSummary { records, documents, files, errors })
+}
+
+/// Extracts the first record of `warc_paths` whose WARC-Record-ID or target URI is `record`
+/// and writes its jusText debug report to `output_path`.
+fn write_debug_report(warc_paths: &[String], record: &str, output_path: &str, options: &ExtractOptions) -> anyhow::Result<()> {
+    let record = record.trim_start_matches('<').trim_end_matches('>');
+    let is_record = |metadata: &RecordMetadata| {
+        metadata.warc_record_id.trim_start_matches('<').trim_end_matches('>') == record || metadata.warc_target_uri.as_deref() == Some(record)
+    };
+    for warc_path in warc_paths {
+        let warc_file = File::open(warc_path).with_context(|| format!("open {}", warc_path))?;
+        let mut warc_reader = input::open_warc_reader(std::io::BufReader::with_capacity(1_048_576, warc_file))?;
+        let mut assembler = RecordAssembler::new(&options.payloads, &options.segments);
+        let mut warc_streaming_iter = warc_reader.stream_records();
+        while let Some(warc_record) = warc_streaming_iter.next_item() {
+            let warc_record = warc_record.with_context(|| format!("read {}", warc_path))?.into_buffered()?;
+            let job = match assembler.push(warc_record) {
+                Some(Ok(job)) if is_record(&job.metadata) => job,
+                _ => continue,
+            };
+            let extract_result = extract_record(job, &options.extractors).map_err(|record_error| record_error.error)?;
+            let debug_html = extract_result.debug_html.ok_or_else(|| anyhow!("record {} is not HTML", record))?;
+            return std::fs::write(output_path, debug_html).with_context(|| format!("write {}", output_path));
+        }
+    }
+    Err(anyhow!("no record {} in {}", record, warc_paths.join(", ")))
+}
 
-    Ok(())
+/// Buffered WARC record waiting for an extract worker.
+struct RecordJob {
+    metadata: RecordMetadata,
+    kind: BlockKind,
+    body: Vec<u8>,
 }
 
-fn iter_contents<R, F>(mut warc_reader: warc::WarcReader<R>, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>, mut f: F)
-    where R: BufRead, F: FnMut(anyhow::Result<ExtractResult>) {
+/// Top-level domain of a URL, e.g. "jp" for http://www.example.co.jp/index.html.
+fn url_tld(url: &str) -> Option<&str> {
+    let host = url.split("://").nth(1)?.split(['/', '?', '#']).next()?;
+    let host = host.rsplit('@').next()?.split(':').next()?;
+    host.rsplit('.').next().filter(|tld| !tld.is_empty() && tld.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+fn extract_record(job: RecordJob, extractors: &Extractors) -> Result<ExtractResult, RecordError> {
+    let result = match job.kind {
+        BlockKind::HttpResponse => extract_content(&job.body, job.metadata.clone(), extractors),
+        BlockKind::Payload => extract_payload(&job.body, job.metadata.clone(), extractors),
+    };
+    let result = result.context("extract_content").map(|mut extract_result| {
+        extract_result.language = language::identify(&extract_result.content).map(|code| code.to_string());
+        extract_result
+    });
+    result.map_err(|error| RecordError { metadata: Some(Box::new(job.metadata)), body: Some(job.body), error })
+}
+
+/// Reads the records of `warc_reader` and passes the extraction result of each response to `f`.
+/// With more than one worker, records are extracted on a thread pool while reading continues;
+/// `ordered` then restores the record order before calling `f`.
+fn iter_contents<R, F>(mut warc_reader: warc::WarcReader<R>, options: &ExtractOptions, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>, mut f: F)
+    where R: BufRead, F: FnMut(Result<ExtractResult, RecordError>) {
+    let workers = options.record_workers;
+    let extractors = options.extractors.as_ref();
     // let mut pbs: Vec<ProgressBar> = vec![];
     // for _ in 0..6 {
     //     let pbi = pb.add(indicatif::ProgressBar::with_draw_target(None, indicatif::ProgressDrawTarget::stderr()));
@@ -150,80 +659,125 @@ fn iter_contents<R, F>(mut warc_reader: warc::WarcReader<R>, pbm: &std::sync::Ar
     //     pbs.push(pbi);
     // }
 
-    let mut warc_streaming_iter = warc_reader.stream_records();
-    while let Some(warc_record_streaming_body) = warc_streaming_iter.next_item() {
-        // pb1.inc(1);
-        match warc_record_streaming_body {
-            Ok(warc_response_record_streaming_body) => {
-                match warc_response_record_streaming_body.warc_type() {
-                    warc::RecordType::Response | warc::RecordType::Continuation => {
-                        // pbs[0].inc(1);
-                        match warc_response_record_streaming_body.into_buffered() {
-                            Ok(warc_response_record_buffered_body) => {
-                                // pb4.inc(1);
-                                let warc_response_body_bytes = warc_response_record_buffered_body.body();
-                                match std::str::from_utf8(warc_response_body_bytes) {
-                                    Ok(warc_response_body_str) => {
-                                        // pb5.inc(1);
-                                        let http_response = warc_response_body_str;
-                                        match extract_content(&http_response) {
-                                            Ok(extract_result) => {
-                                                // pbs[1].inc(1);
-												f(Ok(extract_result))
-                                            }
-                                            Err(e) => f(Err(anyhow!(e)).with_context(|| "extract_content"))
-                                        }
+    let (job_tx, job_rx) = crossbeam_channel::bounded::<(usize, RecordJob)>(workers * 2);
+    let (result_tx, result_rx) = crossbeam_channel::unbounded::<(usize, Result<ExtractResult, RecordError>)>();
+    let mut reorder = ResultOrder::new(options.ordered);
+    let mut assembler = RecordAssembler::new(&options.payloads, &options.segments);
+
+    std::thread::scope(|scope| {
+        if workers > 1 {
+            for _ in 0..workers {
+                let job_rx = job_rx.clone();
+                let result_tx = result_tx.clone();
+                scope.spawn(move || {
+                    for (seq, job) in job_rx {
+                        result_tx.send((seq, extract_record(job, extractors))).unwrap();
+                    }
+                });
+            }
+        }
+        drop(job_rx);
+
+        let mut seq = 0;
+        let mut submit = |job: Result<RecordJob, RecordError>, f: &mut F| {
+            match job {
+                Ok(job) if workers > 1 => job_tx.send((seq, job)).unwrap(),
+                Ok(job) => result_tx.send((seq, extract_record(job, extractors))).unwrap(),
+                Err(record_error) => result_tx.send((seq, Err(record_error))).unwrap(),
+            }
+            seq += 1;
+            for (seq, result) in result_rx.try_iter() {
+                reorder.push(seq, result, f);
+            }
+        };
+
+        let mut warc_streaming_iter = warc_reader.stream_records();
+        while let Some(warc_record_streaming_body) = warc_streaming_iter.next_item() {
+            // pb1.inc(1);
+            match warc_record_streaming_body {
+                Ok(warc_response_record_streaming_body) => {
+                    match warc_response_record_streaming_body.warc_type() {
+                        warc::RecordType::Response | warc::RecordType::Continuation | warc::RecordType::Resource
+                        | warc::RecordType::Conversion | warc::RecordType::Revisit => {
+                            // pbs[0].inc(1);
+                            match warc_response_record_streaming_body.into_buffered() {
+                                Ok(warc_response_record_buffered_body) => {
+                                    // pb4.inc(1);
+                                    if let Some(job) = assembler.push(warc_response_record_buffered_body) {
+                                        submit(job, &mut f);
                                     }
-                                    Err(e) => f(Err(anyhow!(e)).with_context(|| "from_utf8"))
                                 }
+                                Err(e) => submit(Err(RecordError::new(anyhow!(e).context("into_buffered"))), &mut f)
                             }
-                            Err(e) => f(Err(anyhow!(e)).with_context(|| "into_buffered"))
                         }
+                        _ => {}
+                        // _ => pb3.inc(1)
                     }
-                    _ => {}
-                    // _ => pb3.inc(1)
                 }
-            }
-            Err(warc::Error::UnexpectedEOB) => {
-                println!("UnexpectedEOB");
-                break;
-            }
-            Err(e) => f(Err(anyhow!(e)).with_context(|| "next_item"))
-        };
-    }
+                Err(warc::Error::UnexpectedEOB) => {
+                    submit(Err(RecordError::new(anyhow!(warc::Error::UnexpectedEOB).context("next_item"))), &mut f);
+                    break;
+                }
+                Err(e) => submit(Err(RecordError::new(anyhow!(e).context("next_item"))), &mut f)
+            };
+        }
+
+        drop(job_tx);
+        drop(result_tx);
+        for (seq, result) in result_rx.iter() {
+            reorder.push(seq, result, &mut f);
+        }
+    });
 }
 
-fn retrieve_warc_file(warc_path: &str, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<()> {
-    let warc_filename = warc_path.split('/').last().unwrap();
-    let warc_remote_path = format!("{}/{}", CC_REMOTE_PATH, warc_path);
-    let warc_local_path = format!("{}/warcs/{}", LOCAL_BASE_PATH, warc_filename);
-    retrieve_file(&warc_remote_path, &warc_local_path, pbm)
+/// Hands results to the callback either as they arrive or in sequence order.
+struct ResultOrder {
+    ordered: bool,
+    next_seq: usize,
+    pending: std::collections::BTreeMap<usize, Result<ExtractResult, RecordError>>,
 }
 
-fn retrieve_file(remote_path: &str, local_path: &str, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) ->anyhow::Result<()> {
-    if std::path::Path::new(&local_path).exists() {
-        return Ok(());
+impl ResultOrder {
+    fn new(ordered: bool) -> ResultOrder {
+        ResultOrder { ordered, next_seq: 0, pending: std::collections::BTreeMap::new() }
     }
-    let mut response = reqwest::blocking::get(remote_path)?;
 
-    let pb = pbm.lock().unwrap().add(indicatif::ProgressBar::with_draw_target(None, indicatif::ProgressDrawTarget::stdout()));
-    pb.set_style(get_progress_style());
+    fn push<F>(&mut self, seq: usize, result: Result<ExtractResult, RecordError>, f: &mut F) where F: FnMut(Result<ExtractResult, RecordError>) {
+        if !self.ordered {
+            return f(result);
+        }
+        self.pending.insert(seq, result);
+        while let Some(result) = self.pending.remove(&self.next_seq) {
+            f(result);
+            self.next_seq += 1;
+        }
+    }
+}
 
-    pb.set_message(remote_path.split('/').last().unwrap().to_string());
-    pb.set_length(response.content_length().unwrap());
+fn retrieve_warc_file(downloader: &Downloader, warc_path: &str, dirs: &Dirs, pbm: &std::sync::Arc<std::sync::Mutex<indicatif::MultiProgress>>) -> anyhow::Result<()> {
+    let warc_filename = warc_path.rsplit('/').next().unwrap();
+    let warc_remote_path = downloader.remote_path(warc_path);
+    let warc_local_path = format!("{}/{}", dirs.warcs, warc_filename);
+    downloader.retrieve_file(&warc_remote_path, &warc_local_path, pbm)
+}
 
-    let local_path_temp = format!("{}.tmp", local_path);
-    let file = std::fs::File::create(&local_path_temp)?;
-    let mut file = pb.wrap_read(file);
-    std::io::copy(&mut response, &mut file)?;
-    std::fs::rename(&local_path_temp, &local_path)?;
-    Ok(())
+fn select_warc_paths<I, T>(warc_paths: I, shard: Option<Shard>, offset: usize, count: usize) -> impl Iterator<Item=T>
+    where I: Iterator<Item=T> {
+    let shard = shard.unwrap_or(Shard { index: 0, total: 1 });
+    warc_paths
+        .enumerate()
+        .filter(move |(i, _)| i % shard.total == shard.index)
+        .map(|(_, warc_path)| warc_path)
+        .skip(offset)
+        .take(count)
 }
 
 fn read_warc_paths(warcs_path: &str) -> anyhow::Result<impl Iterator<Item=String>> {
-    let file = std::fs::File::open(warcs_path)?;
+    let file = std::fs::File::open(warcs_path).with_context(|| format!("open {}", warcs_path))?;
     let reader = std::io::BufReader::new(file);
-    let decoder = flate2::bufread::GzDecoder::new(reader);
-    let reader = std::io::BufReader::new(decoder);
-    Ok(std::io::BufReader::lines(reader).map(|s| s.unwrap().to_string()))
+    let reader: Box<dyn BufRead> = match warcs_path.ends_with(".gz") {
+        true => Box::new(std::io::BufReader::new(flate2::bufread::GzDecoder::new(reader))),
+        false => Box::new(reader),
+    };
+    Ok(reader.lines().map(|s| s.unwrap().to_string()).filter(|s| !s.is_empty()))
 }
@@ -0,0 +1,63 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use anyhow::Context;
+use crate::errors::ErrorReport;
+use crate::output::OutputFile;
+
+/// Line of the completion manifest, written once all output of a WARC file is in place.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ManifestEntry {
+    pub warc: String,
+    pub records: u64,
+    pub documents: u64,
+    pub files: Vec<OutputFile>,
+    #[serde(default)]
+    pub errors: ErrorReport,
+    pub completed_at: String,
+}
+
+/// Append-only JSONL record of the WARC files whose extraction has completed.
+pub struct Manifest {
+    path: String,
+    completed: HashSet<String>,
+    file: std::sync::Mutex<std::fs::File>,
+}
+
+impl Manifest {
+    pub fn open(path: &str) -> anyhow::Result<Manifest> {
+        let mut completed = HashSet::new();
+        if std::path::Path::new(path).exists() {
+            let reader = std::io::BufReader::new(std::fs::File::open(path).with_context(|| format!("open {}", path))?);
+            for line in reader.lines() {
+                let line = line?;
+                // a run killed mid-write leaves a truncated last line; its WARC is simply redone
+                if let Ok(entry) = serde_json::from_str::<ManifestEntry>(&line) {
+                    completed.insert(entry.warc);
+                }
+            }
+        }
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("open {}", path))?;
+        Ok(Manifest { path: path.to_string(), completed, file: std::sync::Mutex::new(file) })
+    }
+
+    pub fn is_completed(&self, warc: &str) -> bool {
+        self.completed.contains(warc)
+    }
+
+    pub fn complete(&self, warc: &str, records: u64, documents: u64, files: Vec<OutputFile>, errors: ErrorReport) -> anyhow::Result<()> {
+        let entry = ManifestEntry {
+            warc: warc.to_string(),
+            records,
+            documents,
+            files,
+            errors,
+            completed_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes()).with_context(|| format!("write {}", self.path))?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
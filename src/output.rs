@@ -0,0 +1,619 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+use anyhow::Context;
+use sha2::Digest;
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use crate::parser::Paragraph;
+use crate::{ExtractResult, RecordMetadata};
+
+const PARQUET_BATCH_SIZE: usize = 1024;
+const PARQUET_ROW_GROUP_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// `{len}\t{text}` per document
+    Text,
+    /// one JSON object per document with the WARC and HTTP metadata
+    Jsonl,
+    /// Common Crawl style WET file of gzipped `conversion` records
+    Wet,
+    /// Parquet file with one row per document
+    Parquet,
+    /// one JSON object per document with every paragraph, its features and its classes
+    Paragraphs,
+}
+
+impl OutputFormat {
+    /// Output file name for documents from `stem`; `index` numbers the shards of a rotated output.
+    pub fn file_name(&self, stem: &str, index: Option<usize>, compression: Compression) -> String {
+        let index = index.map(|index| format!("-{:05}", index)).unwrap_or_default();
+        match self {
+            OutputFormat::Text => format!("{}_extract{}{}", stem, index, compression.extension()),
+            OutputFormat::Jsonl => format!("{}_extract{}.jsonl{}", stem, index, compression.extension()),
+            OutputFormat::Wet => format!("{}{}.warc.wet.gz", stem, index),
+            OutputFormat::Parquet => format!("{}_extract{}.parquet", stem, index),
+            OutputFormat::Paragraphs => format!("{}_paragraphs{}.jsonl{}", stem, index, compression.extension()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    pub format: OutputFormat,
+    /// Compression of text, JSONL and paragraphs output; WET and Parquet files are always compressed.
+    pub compression: Compression,
+    /// Start a new output file once this many bytes have been written to the current one.
+    pub max_file_bytes: Option<u64>,
+    /// Start a new output file once this many documents have been written to the current one.
+    pub max_file_docs: Option<u64>,
+    /// Write the documents of each language to their own files.
+    pub split_by_language: bool,
+}
+
+impl OutputOptions {
+    fn rotates(&self) -> bool {
+        self.max_file_bytes.is_some() || self.max_file_docs.is_some()
+    }
+}
+
+/// Parses a byte size such as `4096`, `512K`, `256M` or `2G`.
+pub fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1 << 10),
+        Some('M') => (&s[..s.len() - 1], 1 << 20),
+        Some('G') => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+    let size: u64 = digits.parse().with_context(|| format!("invalid size {}", s))?;
+    size.checked_mul(multiplier).ok_or_else(|| anyhow::anyhow!("size {} too large", s))
+}
+
+/// Destination for the documents extracted from one WARC file.
+pub trait Sink {
+    fn write(&mut self, extract_result: &ExtractResult) -> anyhow::Result<()>;
+    /// Approximate size of the current output file; compressed output lags behind by the encoder's buffer.
+    fn bytes_written(&self) -> u64;
+    /// Flushes the output and moves the finished files into place.
+    fn finish(self: Box<Self>) -> anyhow::Result<Vec<OutputFile>>;
+}
+
+/// Output file moved into place by a finished sink.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OutputFile {
+    pub path: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// Output file that is written under a temporary name and renamed once complete,
+/// so that an interrupted run never leaves a partial file that looks finished.
+struct PendingFile {
+    path: String,
+    temp_path: String,
+}
+
+impl PendingFile {
+    fn create(path: &str) -> anyhow::Result<(PendingFile, ChecksumWriter<std::fs::File>)> {
+        let temp_path = format!("{}.tmp", path);
+        let file = std::fs::File::create(&temp_path).with_context(|| format!("create {}", temp_path))?;
+        Ok((PendingFile { path: path.to_string(), temp_path }, ChecksumWriter::new(file)))
+    }
+
+    fn commit(self, writer: ChecksumWriter<std::fs::File>) -> anyhow::Result<OutputFile> {
+        let (file, bytes, sha256) = writer.finish();
+        file.sync_all()?;
+        std::fs::rename(&self.temp_path, &self.path).with_context(|| format!("rename {}", self.temp_path))?;
+        Ok(OutputFile { path: self.path, bytes, sha256 })
+    }
+}
+
+/// Creates the sink for documents extracted from `source`, writing to `dir` under names derived from `stem`.
+pub fn create_sink(dir: &str, stem: &str, source: &str, options: &OutputOptions) -> anyhow::Result<Box<dyn Sink>> {
+    if options.compression != Compression::None && matches!(options.format, OutputFormat::Wet | OutputFormat::Parquet) {
+        return Err(anyhow::anyhow!("--compress only applies to text, jsonl and paragraphs output"));
+    }
+    if options.split_by_language {
+        return Ok(Box::new(LanguageSink::new(dir, stem, source, options)));
+    }
+    match options.rotates() {
+        true => Ok(Box::new(RotatingSink::new(dir, stem, source, options)?)),
+        false => open_sink(&format!("{}/{}", dir, options.format.file_name(stem, None, options.compression)), source, options),
+    }
+}
+
+fn open_sink(path: &str, source: &str, options: &OutputOptions) -> anyhow::Result<Box<dyn Sink>> {
+    let (pending, file) = PendingFile::create(path)?;
+    match options.format {
+        OutputFormat::Text | OutputFormat::Jsonl | OutputFormat::Wet | OutputFormat::Paragraphs => {
+            let encoder = Encoder::new(file, options.compression)?;
+            let mut writer = std::io::BufWriter::new(encoder);
+            write_header(&mut writer, options.format, path.rsplit('/').next().unwrap(), source)?;
+            Ok(Box::new(StreamSink { writer, pending, format: options.format, source: source.to_string() }))
+        }
+        OutputFormat::Parquet => Ok(Box::new(ParquetSink::new(file, pending)?)),
+    }
+}
+
+/// Routes documents to one sink per language, named `{stem}_{language}`, opened on the first document of each.
+struct LanguageSink {
+    dir: String,
+    stem: String,
+    source: String,
+    options: OutputOptions,
+    sinks: BTreeMap<String, Box<dyn Sink>>,
+}
+
+impl LanguageSink {
+    fn new(dir: &str, stem: &str, source: &str, options: &OutputOptions) -> LanguageSink {
+        let options = OutputOptions { split_by_language: false, ..options.clone() };
+        LanguageSink { dir: dir.to_string(), stem: stem.to_string(), source: source.to_string(), options, sinks: BTreeMap::new() }
+    }
+}
+
+impl Sink for LanguageSink {
+    fn write(&mut self, extract_result: &ExtractResult) -> anyhow::Result<()> {
+        let language = extract_result.language.as_deref().unwrap_or(crate::language::UNDETERMINED);
+        if !self.sinks.contains_key(language) {
+            let sink = create_sink(&self.dir, &format!("{}_{}", self.stem, language), &self.source, &self.options)?;
+            self.sinks.insert(language.to_string(), sink);
+        }
+        self.sinks.get_mut(language).unwrap().write(extract_result)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.sinks.values().map(|sink| sink.bytes_written()).sum()
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<Vec<OutputFile>> {
+        let mut finished = vec![];
+        for sink in self.sinks.into_values() {
+            finished.extend(sink.finish()?);
+        }
+        Ok(finished)
+    }
+}
+
+/// Sink for the record-per-line and WET formats.
+struct StreamSink {
+    writer: std::io::BufWriter<Encoder<ChecksumWriter<std::fs::File>>>,
+    pending: PendingFile,
+    format: OutputFormat,
+    source: String,
+}
+
+impl Sink for StreamSink {
+    fn write(&mut self, extract_result: &ExtractResult) -> anyhow::Result<()> {
+        write_record(&mut self.writer, self.format, &self.source, extract_result)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.writer.get_ref().get_ref().bytes + self.writer.buffer().len() as u64
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<Vec<OutputFile>> {
+        let encoder = self.writer.into_inner().map_err(|e| e.into_error())?;
+        let file = encoder.finish()?;
+        Ok(vec![self.pending.commit(file)?])
+    }
+}
+
+/// Splits the output into numbered files once the current one reaches the configured limits.
+struct RotatingSink {
+    dir: String,
+    stem: String,
+    source: String,
+    options: OutputOptions,
+    index: usize,
+    docs: u64,
+    current: Box<dyn Sink>,
+    finished: Vec<OutputFile>,
+}
+
+impl RotatingSink {
+    fn new(dir: &str, stem: &str, source: &str, options: &OutputOptions) -> anyhow::Result<RotatingSink> {
+        let path = format!("{}/{}", dir, options.format.file_name(stem, Some(0), options.compression));
+        Ok(RotatingSink {
+            dir: dir.to_string(),
+            stem: stem.to_string(),
+            source: source.to_string(),
+            options: options.clone(),
+            index: 0,
+            docs: 0,
+            current: open_sink(&path, source, options)?,
+            finished: vec![],
+        })
+    }
+
+    fn is_full(&self) -> bool {
+        self.options.max_file_docs.is_some_and(|max_docs| self.docs >= max_docs)
+            || self.options.max_file_bytes.is_some_and(|max_bytes| self.current.bytes_written() >= max_bytes)
+    }
+
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        self.index += 1;
+        self.docs = 0;
+        let path = format!("{}/{}", self.dir, self.options.format.file_name(&self.stem, Some(self.index), self.options.compression));
+        let previous = std::mem::replace(&mut self.current, open_sink(&path, &self.source, &self.options)?);
+        self.finished.extend(previous.finish()?);
+        Ok(())
+    }
+}
+
+impl Sink for RotatingSink {
+    fn write(&mut self, extract_result: &ExtractResult) -> anyhow::Result<()> {
+        if self.docs > 0 && self.is_full() {
+            self.rotate()?;
+        }
+        self.current.write(extract_result)?;
+        self.docs += 1;
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.current.bytes_written()
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<Vec<OutputFile>> {
+        let mut finished = self.finished;
+        finished.extend(self.current.finish()?);
+        Ok(finished)
+    }
+}
+
+/// Optional compression layer of a stream sink.
+enum Encoder<W: Write> {
+    None(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(writer: W, compression: Compression) -> anyhow::Result<Encoder<W>> {
+        Ok(match compression {
+            Compression::None => Encoder::None(writer),
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(writer, flate2::Compression::default())),
+            Compression::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+        })
+    }
+
+    fn get_ref(&self) -> &W {
+        match self {
+            Encoder::None(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.get_ref(),
+            Encoder::Zstd(encoder) => encoder.get_ref(),
+        }
+    }
+
+    fn finish(self) -> std::io::Result<W> {
+        match self {
+            Encoder::None(writer) => Ok(writer),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Encoder::None(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Encoder::None(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Counts and hashes the bytes that reach the output file.
+struct ChecksumWriter<W: Write> {
+    inner: W,
+    bytes: u64,
+    sha256: sha2::Sha256,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn new(inner: W) -> ChecksumWriter<W> {
+        ChecksumWriter { inner, bytes: 0, sha256: sha2::Sha256::new() }
+    }
+
+    fn finish(self) -> (W, u64, String) {
+        (self.inner, self.bytes, format!("{:x}", self.sha256.finalize()))
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        self.sha256.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonlRecord<'a> {
+    source: &'a str,
+    #[serde(flatten)]
+    metadata: &'a RecordMetadata,
+    language: Option<&'a str>,
+    text: &'a str,
+}
+
+#[derive(serde::Serialize)]
+struct ParagraphsRecord<'a> {
+    source: &'a str,
+    #[serde(flatten)]
+    metadata: &'a RecordMetadata,
+    language: Option<&'a str>,
+    stoplist: Option<&'a str>,
+    paragraphs: Vec<ParagraphRecord<'a>>,
+}
+
+/// Features and classes of one paragraph, as used by jusText.
+#[derive(serde::Serialize)]
+struct ParagraphRecord<'a> {
+    text: &'a str,
+    dom_path: &'a str,
+    tag: &'a str,
+    cfclass: &'a str,
+    finalclass: &'a str,
+    heading: bool,
+    bullet: bool,
+    word_count: i64,
+    linked_char_count: i64,
+    tag_count: i64,
+    stopword_count: i64,
+    stopword_density: f32,
+    link_density: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+}
+
+impl<'a> From<&'a Paragraph> for ParagraphRecord<'a> {
+    fn from(paragraph: &'a Paragraph) -> ParagraphRecord<'a> {
+        ParagraphRecord {
+            text: &paragraph.text,
+            dom_path: &paragraph.dom_path,
+            tag: &paragraph.m_tag,
+            cfclass: paragraph.cfclass,
+            finalclass: paragraph.finalclass,
+            heading: paragraph.heading,
+            bullet: paragraph.bullet,
+            word_count: paragraph.word_count,
+            linked_char_count: paragraph.linked_char_count,
+            tag_count: paragraph.tag_count,
+            stopword_count: paragraph.stopword_count,
+            stopword_density: paragraph.stopword_density,
+            link_density: paragraph.link_density,
+            language: paragraph.language,
+        }
+    }
+}
+
+/// Writes whatever has to precede the first record, i.e. the `warcinfo` record of a WET file.
+pub fn write_header<W: Write>(writer: &mut W, format: OutputFormat, filename: &str, source: &str) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Jsonl | OutputFormat::Parquet | OutputFormat::Paragraphs => {}
+        OutputFormat::Wet => {
+            let body = format!(
+                "Software-Info: {}/{}\r\nsource: {}\r\nformat: WARC File Format 1.0\r\n",
+                env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), source);
+            let date = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+            let record_id = warc::Record::<warc::BufferedBody>::generate_record_id();
+            write_gzip_record(writer, &[
+                ("WARC-Type", "warcinfo"),
+                ("WARC-Date", &date),
+                ("WARC-Filename", filename),
+                ("WARC-Record-ID", &record_id),
+                ("Content-Type", "application/warc-fields"),
+            ], body.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_record<W: Write>(writer: &mut W, format: OutputFormat, source: &str, extract_result: &ExtractResult) -> anyhow::Result<()> {
+    let content = &extract_result.content;
+    match format {
+        OutputFormat::Text => {
+            writer.write_all(format!("{}\t", content.len()).as_bytes())?;
+            writer.write_all(content.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        OutputFormat::Jsonl => {
+            let record = JsonlRecord { source, metadata: &extract_result.metadata, language: extract_result.language.as_deref(), text: content };
+            serde_json::to_writer(&mut *writer, &record)?;
+            writer.write_all(b"\n")?;
+        }
+        OutputFormat::Paragraphs => {
+            let record = ParagraphsRecord {
+                source,
+                metadata: &extract_result.metadata,
+                language: extract_result.language.as_deref(),
+                stoplist: extract_result.stoplist.as_deref(),
+                paragraphs: extract_result.paragraphs.iter().map(ParagraphRecord::from).collect(),
+            };
+            serde_json::to_writer(&mut *writer, &record)?;
+            writer.write_all(b"\n")?;
+        }
+        OutputFormat::Wet => {
+            write_wet_record(writer, &extract_result.metadata, extract_result.language.as_deref(), &wet_text(extract_result))?;
+        }
+        OutputFormat::Parquet => return Err(anyhow::anyhow!("parquet output needs a ParquetSink")),
+    }
+    Ok(())
+}
+
+/// Writes the `conversion` record for a document, deriving its headers from the source `response` record.
+/// Good paragraphs one per line, as in Common Crawl WET files, whatever the jusText output format.
+fn wet_text(extract_result: &ExtractResult) -> String {
+    if extract_result.paragraphs.is_empty() {
+        return extract_result.content.clone();
+    }
+    extract_result.paragraphs.iter()
+        .filter(|paragraph| paragraph.finalclass == "good")
+        .map(|paragraph| paragraph.text.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn write_wet_record<W: Write>(writer: &mut W, metadata: &RecordMetadata, language: Option<&str>, content: &str) -> anyhow::Result<()> {
+    let record_id = warc::Record::<warc::BufferedBody>::generate_record_id();
+    let mut headers = vec![("WARC-Type", "conversion")];
+    if let Some(target_uri) = &metadata.warc_target_uri {
+        headers.push(("WARC-Target-URI", target_uri));
+    }
+    headers.push(("WARC-Date", &metadata.warc_date));
+    headers.push(("WARC-Record-ID", &record_id));
+    headers.push(("WARC-Refers-To", &metadata.warc_record_id));
+    if let Some(language) = language {
+        headers.push(("WARC-Identified-Content-Language", language));
+    }
+    let block_digest = format!("sha1:{}", data_encoding::BASE32.encode(&sha1::Sha1::digest(content.as_bytes())));
+    headers.push(("WARC-Block-Digest", &block_digest));
+    headers.push(("Content-Type", "text/plain"));
+    write_gzip_record(writer, &headers, content.as_bytes())
+}
+
+/// Writes one WARC record as its own gzip member, so that WET readers can seek record by record.
+pub fn write_gzip_record<W: Write>(writer: &mut W, headers: &[(&str, &str)], body: &[u8]) -> anyhow::Result<()> {
+    let mut gzip = flate2::write::GzEncoder::new(&mut *writer, flate2::Compression::default());
+    gzip.write_all(b"WARC/1.0\r\n")?;
+    for (name, value) in headers {
+        gzip.write_all(format!("{}: {}\r\n", name, value).as_bytes())?;
+    }
+    gzip.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())?;
+    gzip.write_all(body)?;
+    gzip.write_all(b"\r\n\r\n")?;
+    gzip.finish()?;
+    Ok(())
+}
+
+/// Columns of the Parquet output, buffered until the next batch is written.
+#[derive(Default)]
+struct ParquetColumns {
+    url: Vec<Option<String>>,
+    warc_record_id: Vec<String>,
+    date: Vec<String>,
+    language: Vec<Option<String>>,
+    encoding: Vec<Option<String>>,
+    text: Vec<String>,
+    paragraph_count: Vec<u32>,
+    good_paragraph_count: Vec<u32>,
+    html_length: Vec<u64>,
+    text_length: Vec<u64>,
+}
+
+struct ParquetSink {
+    writer: parquet::arrow::ArrowWriter<ChecksumWriter<std::fs::File>>,
+    pending: PendingFile,
+    schema: Arc<Schema>,
+    columns: ParquetColumns,
+}
+
+impl ParquetSink {
+    fn new(file: ChecksumWriter<std::fs::File>, pending: PendingFile) -> anyhow::Result<ParquetSink> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("url", DataType::Utf8, true),
+            Field::new("warc_record_id", DataType::Utf8, false),
+            Field::new("date", DataType::Utf8, false),
+            Field::new("language", DataType::Utf8, true),
+            Field::new("encoding", DataType::Utf8, true),
+            Field::new("text", DataType::Utf8, false),
+            Field::new("paragraph_count", DataType::UInt32, false),
+            Field::new("good_paragraph_count", DataType::UInt32, false),
+            Field::new("html_length", DataType::UInt64, false),
+            Field::new("text_length", DataType::UInt64, false),
+        ]));
+        let properties = parquet::file::properties::WriterProperties::builder()
+            .set_compression(parquet::basic::Compression::ZSTD(Default::default()))
+            .set_max_row_group_size(PARQUET_ROW_GROUP_SIZE)
+            .build();
+        let writer = parquet::arrow::ArrowWriter::try_new(file, schema.clone(), Some(properties))?;
+        Ok(ParquetSink { writer, pending, schema, columns: ParquetColumns::default() })
+    }
+
+    fn write_batch(&mut self) -> anyhow::Result<()> {
+        if self.columns.text.is_empty() {
+            return Ok(());
+        }
+        let columns = std::mem::take(&mut self.columns);
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(columns.url)),
+            Arc::new(StringArray::from(columns.warc_record_id)),
+            Arc::new(StringArray::from(columns.date)),
+            Arc::new(StringArray::from(columns.language)),
+            Arc::new(StringArray::from(columns.encoding)),
+            Arc::new(StringArray::from(columns.text)),
+            Arc::new(UInt32Array::from(columns.paragraph_count)),
+            Arc::new(UInt32Array::from(columns.good_paragraph_count)),
+            Arc::new(UInt64Array::from(columns.html_length)),
+            Arc::new(UInt64Array::from(columns.text_length)),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        self.writer.write(&batch)?;
+        Ok(())
+    }
+}
+
+impl Sink for ParquetSink {
+    fn bytes_written(&self) -> u64 {
+        (self.writer.bytes_written() + self.writer.in_progress_size()) as u64
+    }
+
+    fn write(&mut self, extract_result: &ExtractResult) -> anyhow::Result<()> {
+        let metadata = &extract_result.metadata;
+        let columns = &mut self.columns;
+        columns.url.push(metadata.warc_target_uri.clone());
+        columns.warc_record_id.push(metadata.warc_record_id.clone());
+        columns.date.push(metadata.warc_date.clone());
+        columns.language.push(extract_result.language.clone());
+        columns.encoding.push(metadata.encoding.clone());
+        columns.text.push(extract_result.content.clone());
+        columns.paragraph_count.push(extract_result.paragraph_count as u32);
+        columns.good_paragraph_count.push(extract_result.good_paragraph_count as u32);
+        columns.html_length.push(extract_result.html_length as u64);
+        columns.text_length.push(extract_result.content.len() as u64);
+        if columns.text.len() >= PARQUET_BATCH_SIZE {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<Vec<OutputFile>> {
+        self.write_batch()?;
+        let file = self.writer.into_inner()?;
+        Ok(vec![self.pending.commit(file)?])
+    }
+}
@@ -34,6 +34,8 @@ pub struct Paragraph {
     pub stopword_count: i64,
     pub stopword_density: f32,
     pub link_density: f32,
+    /// ISO 639-3 code of the text, if paragraph languages are identified.
+    pub language: Option<&'static str>,
 
     pub m_tag: String,
     pub m_htmlSrc: String,
@@ -190,7 +192,7 @@ impl Parser {
             // curr_paragraph.m_htmlPosition2 = it.offset() + it.length();
 
             if self.m_link {
-                self.m_currParagraph.linked_char_count += content.len() as i64;
+                self.m_currParagraph.linked_char_count += content.chars().count() as i64;
             }
 
             self.m_br = false;
@@ -0,0 +1,52 @@
+/// Worker counts and queue size of the download/extract pipeline.
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    pub download_workers: usize,
+    pub extract_workers: usize,
+    /// Number of downloaded items queued for an extract worker. A download worker blocks
+    /// once the queue is full, still holding the item it finished, so up to
+    /// `queue_depth + download_workers` downloaded items may be waiting.
+    pub queue_depth: usize,
+}
+
+/// Runs `download` and then `extract` on every item, overlapping the downloads of later items
+/// with the extraction of earlier ones. Returns the items that failed in either stage.
+pub fn run<T, D, E>(items: Vec<T>, options: &PipelineOptions, download: D, extract: E) -> Vec<(T, anyhow::Error)>
+    where T: Send, D: Fn(&T) -> anyhow::Result<()> + Sync, E: Fn(&T) -> anyhow::Result<()> + Sync {
+    let (todo_tx, todo_rx) = crossbeam_channel::unbounded();
+    for item in items {
+        todo_tx.send(item).unwrap();
+    }
+    drop(todo_tx);
+
+    let (ready_tx, ready_rx) = crossbeam_channel::bounded(options.queue_depth);
+    let failures = std::sync::Mutex::new(vec![]);
+    std::thread::scope(|scope| {
+        let (download, extract, failures) = (&download, &extract, &failures);
+        for _ in 0..options.download_workers.max(1) {
+            let todo_rx = todo_rx.clone();
+            let ready_tx = ready_tx.clone();
+            scope.spawn(move || {
+                for item in todo_rx {
+                    match download(&item) {
+                        Ok(()) => ready_tx.send(item).unwrap(),
+                        Err(e) => failures.lock().unwrap().push((item, e)),
+                    }
+                }
+            });
+        }
+        drop(ready_tx);
+
+        for _ in 0..options.extract_workers.max(1) {
+            let ready_rx = ready_rx.clone();
+            scope.spawn(move || {
+                for item in ready_rx {
+                    if let Err(e) = extract(&item) {
+                        failures.lock().unwrap().push((item, e));
+                    }
+                }
+            });
+        }
+    });
+    failures.into_inner().unwrap()
+}
@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use anyhow::anyhow;
+use warc::{RecordType, WarcHeader};
+use crate::errors::{RecordError, Skipped};
+use crate::{RecordJob, RecordMetadata};
+
+/// Layout of a record block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    /// HTTP response message, as in `response` records.
+    HttpResponse,
+    /// Bare payload typed by the WARC Content-Type, as in `resource` and `conversion` records.
+    Payload,
+}
+
+/// Blocks of earlier records by payload digest and record ID, for resolving `revisit` records.
+/// Holds at most `max_bytes` of blocks, dropping the oldest first; shared by all WARCs of a run.
+pub struct PayloadCache {
+    max_bytes: u64,
+    inner: Mutex<PayloadCacheInner>,
+}
+
+/// Block of a record with the WARC-Type and layout it had.
+#[derive(Clone)]
+struct CachedBlock {
+    warc_type: String,
+    kind: BlockKind,
+    body: Arc<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct PayloadCacheInner {
+    blocks: HashMap<String, CachedBlock>,
+    order: VecDeque<(Vec<String>, u64)>,
+    bytes: u64,
+}
+
+impl std::fmt::Debug for PayloadCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PayloadCache").field("max_bytes", &self.max_bytes).finish()
+    }
+}
+
+impl PayloadCache {
+    pub fn new(max_bytes: u64) -> PayloadCache {
+        PayloadCache { max_bytes, inner: Mutex::new(PayloadCacheInner::default()) }
+    }
+
+    fn insert(&self, keys: Vec<String>, job: &RecordJob) {
+        let size = job.body.len() as u64;
+        if keys.is_empty() || size > self.max_bytes {
+            return;
+        }
+        let block = CachedBlock { warc_type: job.metadata.warc_type.clone(), kind: job.kind, body: Arc::new(job.body.clone()) };
+        let mut inner = self.inner.lock().unwrap();
+        while inner.bytes + size > self.max_bytes {
+            let (old_keys, old_size) = inner.order.pop_front().unwrap();
+            for key in old_keys {
+                inner.blocks.remove(&key);
+            }
+            inner.bytes -= old_size;
+        }
+        for key in &keys {
+            inner.blocks.insert(key.clone(), block.clone());
+        }
+        inner.order.push_back((keys, size));
+        inner.bytes += size;
+    }
+
+    fn get(&self, key: &str) -> Option<CachedBlock> {
+        self.inner.lock().unwrap().blocks.get(key).cloned()
+    }
+}
+
+/// Segmented records waiting for their other segments, by the record ID of their first segment.
+/// Shared by all WARCs of a run, since a record split across WARC files continues in a later one.
+#[derive(Default)]
+pub struct PendingSegments {
+    inner: Mutex<HashMap<String, Segments>>,
+}
+
+#[derive(Default)]
+struct Segments {
+    /// Job of the first segment, once read.
+    job: Option<RecordJob>,
+    payload_digest: Option<String>,
+    parts: BTreeMap<u64, Vec<u8>>,
+    /// Number of the last segment, once read.
+    last: Option<u64>,
+}
+
+impl std::fmt::Debug for PendingSegments {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingSegments").field("records", &self.inner.lock().unwrap().len()).finish()
+    }
+}
+
+impl PendingSegments {
+    /// Errors for the segmented records still missing segments at the end of the run.
+    pub fn finish(&self) -> Vec<RecordError> {
+        self.inner.lock().unwrap().drain()
+            .map(|(origin_id, segments)| match segments.job {
+                Some(job) => record_error(job.metadata, anyhow!("segmented record incomplete, {} segments read", segments.parts.len())),
+                None => RecordError::new(anyhow!("continuation of unseen record {}, {} segments read", origin_id, segments.parts.len()).context("assemble_record")),
+            })
+            .collect()
+    }
+}
+
+/// Turns the records of one WARC into extraction jobs: joins segmented records, passes
+/// `resource` and `conversion` payloads through and resolves `revisit` records.
+pub struct RecordAssembler<'a> {
+    payloads: &'a PayloadCache,
+    segments: &'a PendingSegments,
+}
+
+impl<'a> RecordAssembler<'a> {
+    pub fn new(payloads: &'a PayloadCache, segments: &'a PendingSegments) -> RecordAssembler<'a> {
+        RecordAssembler { payloads, segments }
+    }
+
+    /// The job for `record`, if it completes a document.
+    pub fn push(&mut self, record: warc::Record<warc::BufferedBody>) -> Option<Result<RecordJob, RecordError>> {
+        let header = |name: WarcHeader| record.header(name).map(|value| value.trim().to_string());
+        let mut metadata = RecordMetadata::from_record(&record);
+        let segment_number = header(WarcHeader::SegmentNumber).and_then(|number| number.parse::<u64>().ok());
+        let kind = match record.warc_type() {
+            RecordType::Response => BlockKind::HttpResponse,
+            RecordType::Resource | RecordType::Conversion => BlockKind::Payload,
+            RecordType::Continuation => {
+                let origin_id = match header(WarcHeader::SegmentOriginID) {
+                    Some(origin_id) => origin_id,
+                    None => return Some(Err(record_error(metadata, anyhow!("continuation without WARC-Segment-Origin-ID")))),
+                };
+                let mut pending = self.segments.inner.lock().unwrap();
+                let segments = pending.entry(origin_id.clone()).or_default();
+                // the first segment may not have been read yet
+                let number = segment_number.unwrap_or(segments.parts.len() as u64 + 2);
+                segments.parts.insert(number, record.body().to_vec());
+                if header(WarcHeader::SegmentTotalLength).is_some() {
+                    segments.last = Some(number);
+                }
+                return self.complete(pending, &origin_id);
+            }
+            RecordType::Revisit => {
+                let refers_to = header(WarcHeader::RefersTo);
+                let payload_digest = header(WarcHeader::PayloadDigest);
+                return Some(match refers_to.iter().chain(payload_digest.iter()).find_map(|key| self.payloads.get(key)) {
+                    // the original response, under the revisit's URI and date
+                    Some(block) => {
+                        metadata.warc_type = block.warc_type;
+                        Ok(RecordJob { metadata, kind: block.kind, body: block.body.to_vec() })
+                    }
+                    None if self.payloads.max_bytes == 0 => Err(record_error(metadata, anyhow!(Skipped("revisit cache disabled".to_string())))),
+                    None => Err(record_error(metadata, anyhow!(Skipped("revisit of unseen payload".to_string())))),
+                });
+            }
+            _ => return None,
+        };
+        if segment_number == Some(1) {
+            let record_id = metadata.warc_record_id.clone();
+            let mut pending = self.segments.inner.lock().unwrap();
+            let segments = pending.entry(record_id.clone()).or_default();
+            segments.parts.insert(1, record.body().to_vec());
+            segments.job = Some(RecordJob { metadata, kind, body: vec![] });
+            segments.payload_digest = header(WarcHeader::PayloadDigest);
+            return self.complete(pending, &record_id);
+        }
+        let job = RecordJob { metadata, kind, body: record.body().to_vec() };
+        self.remember(&job, header(WarcHeader::PayloadDigest));
+        Some(Ok(job))
+    }
+
+    /// The job of the segmented record `origin_id` once all its segments are read.
+    fn complete(&self, mut pending: std::sync::MutexGuard<HashMap<String, Segments>>, origin_id: &str) -> Option<Result<RecordJob, RecordError>> {
+        let segments = pending.get(origin_id)?;
+        if segments.job.is_none() || segments.last.is_none_or(|last| segments.parts.len() as u64 != last) {
+            return None;
+        }
+        let segments = pending.remove(origin_id).unwrap();
+        drop(pending);
+        let mut job = segments.job.unwrap();
+        job.body = segments.parts.into_values().flatten().collect();
+        self.remember(&job, segments.payload_digest);
+        Some(Ok(job))
+    }
+
+    fn remember(&self, job: &RecordJob, payload_digest: Option<String>) {
+        let keys = std::iter::once(job.metadata.warc_record_id.clone()).chain(payload_digest).collect();
+        self.payloads.insert(keys, job);
+    }
+}
+
+fn record_error(metadata: RecordMetadata, error: anyhow::Error) -> RecordError {
+    RecordError { metadata: Some(Box::new(metadata)), body: None, error: error.context("assemble_record") }
+}
@@ -0,0 +1,67 @@
+use unicode_segmentation::UnicodeSegmentation;
+use crate::stoplists::Stoplist;
+
+/// Stoplist languages written without spaces between words.
+const UNSPACED_LANGUAGES: [&str; 6] = ["Chinese", "Japanese", "Thai", "Lao", "Khmer", "Burmese"];
+
+/// How paragraph text is split into words for stopword counting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Segmentation {
+    /// Runs of non-whitespace, as the original jusText port did
+    #[default]
+    Whitespace,
+    /// Unicode (UAX #29) words, lowercased, without punctuation
+    Unicode,
+    /// Letters one by one, with stopwords matched as substrings; for scripts without spaces
+    Characters,
+}
+
+impl Segmentation {
+    /// Default segmentation for the stoplist of the language `name`, e.g. "Japanese".
+    pub fn for_language(name: &str) -> Segmentation {
+        match UNSPACED_LANGUAGES.iter().any(|language| language.eq_ignore_ascii_case(name)) {
+            true => Segmentation::Characters,
+            false => Segmentation::Whitespace,
+        }
+    }
+
+    /// Number of stopwords and number of words in `text`. With `Characters` both count
+    /// letters: those covered by stopwords and all of them.
+    pub fn count(&self, text: &str, stoplist: &Stoplist) -> (usize, usize) {
+        match self {
+            Segmentation::Whitespace => {
+                let words: Vec<&str> = text.split(|c: char| c.is_whitespace()).collect();
+                (words.iter().filter(|word| stoplist.contains(word)).count(), words.len())
+            }
+            Segmentation::Unicode => {
+                let words: Vec<String> = text.unicode_words().map(|word| word.to_lowercase()).collect();
+                (words.iter().filter(|word| stoplist.contains(word)).count(), words.len())
+            }
+            Segmentation::Characters => count_stopword_chars(text, stoplist),
+        }
+    }
+}
+
+/// Greedy longest match of stopwords over the letters of `text`.
+fn count_stopword_chars(text: &str, stoplist: &Stoplist) -> (usize, usize) {
+    let chars: Vec<char> = text.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect();
+    let max_len = stoplist.max_word_chars();
+    let mut stopword_chars = 0;
+    let mut i = 0;
+    let mut candidate = String::new();
+    while i < chars.len() {
+        let longest = (1..=max_len.min(chars.len() - i)).rev().find(|&len| {
+            candidate.clear();
+            candidate.extend(&chars[i..i + len]);
+            stoplist.contains(&candidate)
+        });
+        match longest {
+            Some(len) => {
+                stopword_chars += len;
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+    (stopword_chars, chars.len())
+}
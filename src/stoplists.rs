@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use anyhow::{anyhow, Context};
+use crate::parser::Paragraph;
+use crate::segmentation::Segmentation;
+
+/// Stoplists compiled into the binary, by language name.
+const BUNDLED: [(&str, &str); 2] = [
+    ("English", include_str!("../stoplists/English.txt")),
+    ("Hungarian", include_str!("../stoplists/Hungarian.txt")),
+];
+
+/// Stopwords of one language.
+#[derive(Debug, Clone, Default)]
+pub struct Stoplist {
+    pub name: String,
+    pub segmentation: Segmentation,
+    words: HashSet<String>,
+    max_word_chars: usize,
+}
+
+impl Stoplist {
+    /// Parses a stoplist file of one lowercase word per line.
+    pub fn parse(name: &str, text: &str) -> Stoplist {
+        let words: HashSet<String> = text.lines().map(|line| line.trim().to_lowercase()).filter(|word| !word.is_empty()).collect();
+        let max_word_chars = words.iter().map(|word| word.chars().count()).max().unwrap_or(0);
+        Stoplist { name: name.to_string(), segmentation: Segmentation::for_language(name), words, max_word_chars }
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+
+    /// Length of the longest stopword in characters.
+    pub fn max_word_chars(&self) -> usize {
+        self.max_word_chars
+    }
+
+    /// Number of stopwords and number of words in `text`, as split by the stoplist's segmentation.
+    pub fn count(&self, text: &str) -> (usize, usize) {
+        self.segmentation.count(text, self)
+    }
+}
+
+/// How the stoplist of a document is chosen.
+#[derive(Debug, Clone)]
+pub enum StoplistSelection {
+    /// The same stoplist for every document.
+    Fixed(Arc<Stoplist>),
+    /// The stoplist of the document's identified language, else the densest one.
+    Language,
+    /// The stoplist with the highest stopword density over the document, as jusText does
+    /// when no language is given.
+    Density,
+}
+
+/// Stoplists by language name, e.g. "English".
+#[derive(Debug, Clone, Default)]
+pub struct Stoplists {
+    by_name: BTreeMap<String, Arc<Stoplist>>,
+}
+
+impl Stoplists {
+    pub fn bundled() -> Stoplists {
+        let by_name = BUNDLED.iter().map(|(name, text)| (name.to_string(), Arc::new(Stoplist::parse(name, text)))).collect();
+        Stoplists { by_name }
+    }
+
+    /// Adds the `<Language>.txt` files of `dir`, replacing bundled stoplists of the same name.
+    pub fn load_dir(&mut self, dir: &str) -> anyhow::Result<()> {
+        for entry in std::fs::read_dir(dir).with_context(|| format!("read {}", dir))? {
+            let path = entry?.path();
+            let name = match (path.file_stem().and_then(|stem| stem.to_str()), path.extension()) {
+                (Some(name), Some(extension)) if extension == "txt" => name.to_string(),
+                _ => continue,
+            };
+            let text = std::fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+            self.by_name.insert(name.clone(), Arc::new(Stoplist::parse(&name, &text)));
+        }
+        Ok(())
+    }
+
+    /// Splits words with `segmentation` for every language instead of the language's default.
+    pub fn set_segmentation(&mut self, segmentation: Segmentation) {
+        for stoplist in self.by_name.values_mut() {
+            Arc::make_mut(stoplist).segmentation = segmentation;
+        }
+    }
+
+    /// Stoplist by language name, ignoring case.
+    pub fn get(&self, name: &str) -> anyhow::Result<Arc<Stoplist>> {
+        self.by_name.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, stoplist)| stoplist.clone())
+            .ok_or_else(|| anyhow!("no stoplist {}, have {}", name, self.by_name.keys().cloned().collect::<Vec<String>>().join(", ")))
+    }
+
+    /// Stoplist of an ISO 639-3 language code such as "hun", if there is one.
+    pub fn for_language(&self, code: &str) -> Option<Arc<Stoplist>> {
+        let name = match code {
+            // whatlang calls it Mandarin
+            "cmn" => "Chinese",
+            code => whatlang::Lang::from_code(code)?.eng_name(),
+        };
+        self.get(name).ok()
+    }
+
+    /// Stoplist with the highest stopword density over all paragraphs.
+    pub fn densest(&self, paragraphs: &[Paragraph]) -> Option<Arc<Stoplist>> {
+        let mut best: Option<(f32, &Arc<Stoplist>)> = None;
+        for stoplist in self.by_name.values() {
+            let (stopwords, words) = paragraphs.iter()
+                .map(|paragraph| stoplist.count(&paragraph.text))
+                .fold((0, 0), |(s, w), (stopwords, words)| (s + stopwords, w + words));
+            let density = stopwords as f32 / words.max(1) as f32;
+            if best.is_none_or(|(best_density, _)| density > best_density) {
+                best = Some((density, stoplist));
+            }
+        }
+        best.map(|(_, stoplist)| stoplist.clone())
+    }
+
+    /// Stoplist for the paragraphs of one document.
+    pub fn select(&self, selection: &StoplistSelection, paragraphs: &[Paragraph]) -> Arc<Stoplist> {
+        let stoplist = match selection {
+            StoplistSelection::Fixed(stoplist) => return stoplist.clone(),
+            StoplistSelection::Language => {
+                let text: Vec<&str> = paragraphs.iter().map(|paragraph| paragraph.text.as_str()).collect();
+                crate::language::identify(&text.join("\n"))
+                    .and_then(|code| self.for_language(code))
+                    .or_else(|| self.densest(paragraphs))
+            }
+            StoplistSelection::Density => self.densest(paragraphs),
+        };
+        stoplist.unwrap_or_default()
+    }
+}
@@ -1,3 +1,8 @@
+use std::borrow::Cow;
+use encoding_rs::Encoding;
+use lazy_static::lazy_static;
+use regex::Regex;
+
 /**
 str: input
 leftAndRight: left ('l'), right('r') or both ('b'), def: 'b'
@@ -27,42 +32,78 @@ fn trim(str: &str, left_and_right: char, punctuation: bool) -> String {
     re.replace_all(&mut out, "").to_string()
 }
 
-// fn detect_code_page(html: &str) -> String {
-//     let mut out = String::new();
-//     let mut opt = pcre2::options().caseless(true);
-//
-//     let r1 = pcre2::compile(
-// 		r#"meta\s+http-equiv=['"]?content-type['"]?\s+content=['"]?[^'"]*charset=([^'"]+)"#,
-// 		opt,
-// 	).unwrap();
-//     let r2 = pcre2::compile(
-// 		r#"meta\s+content=['"]?[^'"]*charset=([^'"]+)['"]?\s+http-equiv=['"]?content-type['"]?"#,
-// 		opt,
-// 	).unwrap();
-//     let r3 = pcre2::compile(
-// 		r#"meta\s+http-equiv=['"]?charset['"]?\s+content=['"]?([^'"]+)"#,
-// 		opt,
-// 	).unwrap();
-//     let r4 = pcre2::compile(
-// 		r#"meta\s+content=['"]?([^'"]+)['"]?\s+http-equiv=['"]?charset['"]?"#,
-// 		opt,
-// 	).unwrap();
-//     let r5 = pcre2::compile(
-// 		r#"meta\s+charset=['"]?([^'"]+)"#,
-// 		opt,
-// 	).unwrap();
-//
-//     if r1.partial_match(html, &mut out).is_some() {
-//         return out.clone();
-//     } else if r2.partial_match(html, &mut out).is_some() {
-//         return out.clone();
-//     } else if r3.partial_match(html, &mut out).is_some() {
-//         return out.clone();
-//     } else if r4.partial_match(html, &mut out).is_some() {
-//         return out.clone();
-//     } else if r5.partial_match(html, &mut out).is_some() {
-//         return out.clone();
-//     }
-//
-//     String::new()
-// }
+lazy_static! {
+    static ref CODE_PAGE_RES: Vec<Regex> = [
+        r#"(?i)meta\s+http-equiv=['"]?content-type['"]?\s+content=['"]?[^'"]*charset=([^'"]+)"#,
+        r#"(?i)meta\s+content=['"]?[^'"]*charset=([^'"]+)['"]?\s+http-equiv=['"]?content-type['"]?"#,
+        r#"(?i)meta\s+http-equiv=['"]?charset['"]?\s+content=['"]?([^'"]+)"#,
+        r#"(?i)meta\s+content=['"]?([^'"]+)['"]?\s+http-equiv=['"]?charset['"]?"#,
+        r#"(?i)meta\s+charset=['"]?([^'"\s/>]+)"#,
+    ].iter().map(|re| Regex::new(re).unwrap()).collect();
+}
+
+/// Number of leading bytes of a document searched for a `<meta>` charset declaration.
+const CODE_PAGE_PRESCAN_BYTES: usize = 4096;
+
+/// Returns the charset declared by a `<meta>` tag of `html`, or an empty string.
+pub fn detect_code_page(html: &str) -> String {
+    for re in CODE_PAGE_RES.iter() {
+        if let Some(captures) = re.captures(html) {
+            return captures[1].trim().to_string();
+        }
+    }
+    String::new()
+}
+
+/// Returns the `charset` parameter of a Content-Type header value, or an empty string.
+pub fn content_type_charset(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+        .unwrap_or_default()
+}
+
+/// Decodes an HTML document to UTF-8. The encoding is taken from a byte order mark, then the
+/// HTTP Content-Type charset, then a `<meta>` declaration, and is guessed by chardetng otherwise;
+/// `tld` (e.g. "jp") helps the guess. Returns the text and the name of the encoding used.
+pub fn decode_html<'a>(body: &'a [u8], content_type: Option<&str>, tld: Option<&str>) -> (Cow<'a, str>, &'static str) {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(|content_type| Encoding::for_label(content_type_charset(content_type).as_bytes())))
+        .or_else(|| {
+            let prescan = String::from_utf8_lossy(&body[..body.len().min(CODE_PAGE_PRESCAN_BYTES)]);
+            // a meta tag cannot really declare UTF-16, the document would not be readable as ASCII
+            Encoding::for_label(detect_code_page(&prescan).as_bytes()).map(|encoding| match encoding {
+                encoding if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE => encoding_rs::UTF_8,
+                encoding => encoding,
+            })
+        })
+        .unwrap_or_else(|| {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(body, true);
+            detector.guess(tld.map(|tld| tld.as_bytes()), true)
+        });
+    let (text, encoding, _) = encoding.decode(body);
+    (text, encoding.name())
+}
+
+lazy_static! {
+    static ref XML_ENCODING_RE: Regex = Regex::new(r#"^\s*<\?xml[^>]*\sencoding=['"]([^'"]+)['"]"#).unwrap();
+}
+
+/// Decodes an XML document to UTF-8. The encoding is taken from a byte order mark, then the
+/// HTTP Content-Type charset, then the XML declaration, and is UTF-8 otherwise.
+pub fn decode_xml<'a>(body: &'a [u8], content_type: Option<&str>) -> (Cow<'a, str>, &'static str) {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(|content_type| Encoding::for_label(content_type_charset(content_type).as_bytes())))
+        .or_else(|| {
+            let prolog = String::from_utf8_lossy(&body[..body.len().min(256)]);
+            XML_ENCODING_RE.captures(&prolog).and_then(|captures| Encoding::for_label(captures[1].as_bytes()))
+        })
+        .unwrap_or(encoding_rs::UTF_8);
+    let (text, encoding, _) = encoding.decode(body);
+    (text, encoding.name())
+}